@@ -0,0 +1,85 @@
+//往本机的频谱监视器发网络PCM流 用来测试网络音源
+//用法: cargo run --example pcm_sender -- [udp|tcp] [地址:端口] [丢包率]
+//例如: cargo run --example pcm_sender -- udp 127.0.0.1:9000 0.01
+use std::{
+    f32::consts::TAU,
+    io::Write,
+    net::{TcpStream, UdpSocket},
+    time::{Duration, Instant},
+};
+
+//和接收端用同一个格式定义 格式字节不会对不上
+#[allow(dead_code)]
+#[path = "../src/pcm.rs"]
+mod pcm;
+use pcm::RawFormat;
+
+const RATE: u32 = 48000;
+const CHANNELS: u16 = 2;
+const FRAMES: usize = 480; //每包10毫秒
+
+//包头格式见src/network.rs
+fn packet(seq: u32, pcm: &[i16]) -> Vec<u8> {
+    let mut p = Vec::with_capacity(20 + pcm.len() * 2);
+    p.extend_from_slice(b"SPCM");
+    p.extend_from_slice(&seq.to_le_bytes());
+    p.extend_from_slice(&RATE.to_le_bytes());
+    p.extend_from_slice(&CHANNELS.to_le_bytes());
+    p.push(RawFormat::S16LE as u8);
+    p.push(0);
+    p.extend_from_slice(&((pcm.len() * 2) as u32).to_le_bytes());
+    for s in pcm {
+        p.extend_from_slice(&s.to_le_bytes());
+    }
+    p
+}
+
+enum Sink {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+}
+impl Sink {
+    fn send(&mut self, p: &[u8]) -> std::io::Result<()> {
+        match self {
+            Sink::Udp(socket) => socket.send(p).map(|_| ()),
+            Sink::Tcp(stream) => stream.write_all(p),
+        }
+    }
+}
+
+fn main() -> Result<(), anyhow::Error> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let protocol = args.first().map_or("udp", |s| s.as_str());
+    let addr = args.get(1).map_or("127.0.0.1:9000", |s| s.as_str());
+    let loss: f32 = args.get(2).map_or(Ok(0.0), |s| s.parse())?;
+
+    let mut sink = match protocol {
+        "tcp" => Sink::Tcp(TcpStream::connect(addr)?),
+        _ => {
+            let socket = UdpSocket::bind("0.0.0.0:0")?;
+            socket.connect(addr)?;
+            Sink::Udp(socket)
+        }
+    };
+
+    //左声道1kHz 右声道3kHz
+    let mut phase = [0.0f32; 2];
+    let freqs = [1000.0, 3000.0];
+    let start = Instant::now();
+    for seq in 0u32.. {
+        let mut pcm = Vec::with_capacity(FRAMES * CHANNELS as usize);
+        for _ in 0..FRAMES {
+            for c in 0..CHANNELS as usize {
+                pcm.push(((phase[c] * TAU).sin() * 0.1 * i16::MAX as f32) as i16);
+                phase[c] = (phase[c] + freqs[c] / RATE as f32).fract();
+            }
+        }
+        //按丢包率故意不发 测试丢包检测
+        if rand::random::<f32>() >= loss {
+            sink.send(&packet(seq, &pcm))?;
+        }
+        let due = start + Duration::from_secs_f64((seq + 1) as f64 * FRAMES as f64 / RATE as f64);
+        std::thread::sleep(due.saturating_duration_since(Instant::now()));
+    }
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use std::path::PathBuf;
+
+use crate::{network::Protocol, pcm::RawFormat, pipe::PipeInput};
+
+const USAGE: &str = "用法: spectrum_monitor [文件]
+       spectrum_monitor (--stdin | --pipe 路径) [--rate 48000] [--channels 1] [--format s16le]
+       spectrum_monitor (--udp 端口 | --tcp 端口)";
+
+//命令行参数
+#[derive(Debug, Clone)]
+pub struct Args {
+    pub file: Option<PathBuf>,            //启动后直接播放分析的音频文件
+    pub pipe: Option<PipeInput>,          //从标准输入或者命名管道读原始PCM
+    pub network: Option<(Protocol, u16)>, //在端口上收网络PCM流
+    pub rate: u32,
+    pub channels: usize,
+    pub format: RawFormat,
+}
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            file: None,
+            pipe: None,
+            network: None,
+            rate: 48000,
+            channels: 1,
+            format: RawFormat::S16LE,
+        }
+    }
+}
+impl Args {
+    pub fn parse() -> Result<Self, anyhow::Error> {
+        let mut args = Self::default();
+        let mut iter = std::env::args().skip(1);
+        while let Some(arg) = iter.next() {
+            let mut value = || {
+                iter.next()
+                    .ok_or_else(|| anyhow::anyhow!("{arg} 后面缺少参数值\n{USAGE}"))
+            };
+            match arg.as_str() {
+                "--stdin" => args.pipe = Some(PipeInput::Stdin),
+                "--pipe" => args.pipe = Some(PipeInput::Fifo(PathBuf::from(value()?))),
+                "--udp" => args.network = Some((Protocol::Udp, value()?.parse()?)),
+                "--tcp" => args.network = Some((Protocol::Tcp, value()?.parse()?)),
+                "--rate" => args.rate = value()?.parse()?,
+                "--channels" => args.channels = value()?.parse()?,
+                "--format" => {
+                    let name = value()?;
+                    args.format = RawFormat::parse(&name)
+                        .ok_or_else(|| anyhow::anyhow!("未知的采样格式 {name}"))?;
+                }
+                "-h" | "--help" => return Err(anyhow::anyhow!(USAGE)),
+                _ if arg.starts_with('-') => {
+                    return Err(anyhow::anyhow!("未知的参数 {arg}\n{USAGE}"))
+                }
+                _ => {
+                    if args.file.is_some() {
+                        return Err(anyhow::anyhow!("只能指定一个文件\n{USAGE}"));
+                    }
+                    args.file = Some(PathBuf::from(arg));
+                }
+            }
+        }
+        let inputs = [
+            args.file.is_some(),
+            args.pipe.is_some(),
+            args.network.is_some(),
+        ];
+        if inputs.into_iter().filter(|i| *i).count() > 1 {
+            return Err(anyhow::anyhow!("文件、管道和网络只能选一个\n{USAGE}"));
+        }
+        Ok(args)
+    }
+}
@@ -1,149 +1,821 @@
 use std::result::Result::Ok;
-use rustfft::num_complex::ComplexFloat;
-use std::sync::mpsc;
+use realfft::{num_complex::Complex, RealFftPlanner, RealToComplex};
+use std::sync::{mpsc, Arc};
+
+use crate::{
+    cqt::{Cqt, CqtJob, CqtSettings},
+    mel::{MelFilterbank, MelSettings},
+    multitaper::{Multitaper, MultitaperSettings},
+    playback::Transport,
+    reassign::{ReassignMode, Reassigner},
+    window::FFTWindow,
+    zoom::{Zoom, ZoomSettings},
+};
 
 use cpal::{
     traits::{DeviceTrait, HostTrait, StreamTrait},
-    SampleRate, Stream, SupportedStreamConfig,
+    Device, FromSample, SampleFormat, SizedSample, Stream, SupportedStreamConfig,
 };
 
 pub struct Audio {
-    stream: Option<Stream>,
+    source: Box<dyn AudioSource>,
     rx: mpsc::Receiver<Vec<f32>>,
     tx: mpsc::Sender<Vec<f32>>,
     fftsize: usize,
+    window_len: usize, //加窗的长度 比fftsize短的部分补零
+    hop: usize, //帧移 每出一帧频谱往前走多少个采样
     fftwindow: FFTWindow,
+    unit: SpectrumUnit,
+    channel_mode: ChannelMode,
+    buffers: Vec<Vec<f32>>, //按声道模式拆分后 每一路还没做fft的数据
+    planner: RealFftPlanner<f32>,
+    fft: FftCache,
+    zoom: Option<Zoom>, //缩放fft模式 只看一小段频带
+    analysis: Analysis,
+    cqt: Option<Cqt>,
+    cqt_pending: Option<CqtJob>, //后台正在算的常数Q 换掉的时候旧的线程自己会停
+    mel: Option<MelFilterbank>,
+    reassign: Option<Reassigner>,
+    multitaper: Option<Multitaper>,
 }
+//用什么方法从一帧数据得到频谱
 #[derive(Debug, Clone, Copy, PartialEq)]
-pub enum FFTWindow {
-    Rectangular,
-    Hanning,
-    Hamming,
-    Blackman,
+pub enum Analysis {
+    Fft,
+    Cqt(CqtSettings),               //常数Q变换 频点按对数分布
+    Mel(MelSettings),               //fft的功率谱再过一组梅尔滤波器
+    Reassigned(ReassignMode),       //fft的能量挪到时频重心上
+    Multitaper(MultitaperSettings), //几个Slepian窗的功率谱取平均
 }
-impl Audio {
-    fn create_stream(tx: mpsc::Sender<Vec<f32>>) -> Result<Stream, anyhow::Error> {
-        let host = cpal::default_host();
-        let device = host.default_input_device().expect("找不到默认输入设备");
-        let config = device.default_input_config()?;
+//幅度平方或者功率换算成dB 加一点点免得log10(0)
+pub fn power_to_db(power: f32) -> f32 {
+    10.0 * (power + 1e-20).log10()
+}
+//做fft要用的东西 fft大小和窗函数不变就一直复用 不用每帧重新规划和算cos
+struct FftCache {
+    plan: Arc<dyn RealToComplex<f32>>,
+    window: Vec<f32>,  //窗函数表 长度是窗长
+    window_sum: f32,   //窗的相干增益 Σw
+    window_power: f32, //Σw² 算等效噪声带宽用
+    input: Vec<f32>,
+    output: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+}
+impl FftCache {
+    fn new(
+        planner: &mut RealFftPlanner<f32>,
+        fftsize: usize,
+        window_len: usize,
+        window_func: FFTWindow,
+    ) -> Self {
+        let plan = planner.plan_fft_forward(fftsize);
+        let mut cache = Self {
+            window: Vec::new(),
+            window_sum: 0.0,
+            window_power: 0.0,
+            input: plan.make_input_vec(),
+            output: plan.make_output_vec(),
+            scratch: plan.make_scratch_vec(),
+            plan,
+        };
+        cache.set_window(window_func.table(window_len));
+        cache
+    }
+    fn set_window(&mut self, window: Vec<f32>) {
+        self.window_sum = window.iter().sum();
+        self.window_power = window.iter().map(|w| w * w).sum();
+        self.window = window;
+    }
+    /*
+    把|X|²校准成满幅正弦为1的功率 传进来的已经乘好了单边的系数
+    幅度谱: 除以窗的相干增益的平方(Σw)² 正弦的峰值就是它的幅度 满幅正弦正好是0dB
+    功率谱密度: 再除以等效噪声带宽 fs·Σw²/(Σw)² 单位是dBFS/Hz
+    */
+    fn calibrate(&self, unit: SpectrumUnit, sample_rate: f32, power: Vec<f32>) -> Vec<f32> {
+        let scale = match unit {
+            SpectrumUnit::Dbfs => 1.0 / (self.window_sum * self.window_sum),
+            SpectrumUnit::Psd => 1.0 / (sample_rate * self.window_power),
+        };
+        power.into_iter().map(|p| p * scale).collect()
+    }
+}
+//频谱输出的单位
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpectrumUnit {
+    Dbfs, //满幅正弦是0dB 换窗换fft大小 正弦的读数都不变
+    Psd,  //功率谱密度 dBFS/Hz 噪声的读数不随窗和fft大小变
+}
+//多声道数据怎么变成要分析的信号
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChannelMode {
+    Channel(usize), //只看某一个声道 从0开始数
+    Average,        //所有声道取平均
+    Mid,            //(L+R)/2
+    Side,           //(L-R)/2
+    All,            //每个声道单独分析
+}
+//用主机名和设备名来标识一个输入设备 方便保存到配置文件里
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceId {
+    pub host: String,
+    pub name: String,
+}
+//枚举出来的输入设备信息 给界面展示用
+#[derive(Debug, Clone)]
+pub struct DeviceInfo {
+    pub id: DeviceId,
+    pub is_default: bool,
+    pub channels: Vec<u16>,
+    pub sample_rates: Vec<(u32, u32)>, //支持的采样率范围 (最小, 最大)
+    pub formats: Vec<SampleFormat>,
+}
+//音频来源 麦克风、文件、信号发生器之类的都实现这个
+//数据是交错排列的f32 声道数由channels给出 Audio负责后面的声道转换和fft
+pub trait AudioSource {
+    //开始产生数据 推送式的音源把数据块发到tx里
+    fn start(&mut self, tx: mpsc::Sender<Vec<f32>>) -> Result<(), anyhow::Error>;
+    fn stop(&mut self);
+    fn sample_rate(&self) -> u32;
+    fn channels(&self) -> usize;
+    //拉取式的音源在这里返回数据块 fetch_data会一直拉到返回None为止
+    fn pull(&mut self) -> Option<Vec<f32>> {
+        None
+    }
+    //文件之类能暂停和跳转的音源返回它的播放控制
+    fn transport(&self) -> Option<Arc<Transport>> {
+        None
+    }
+    //给界面显示的状态 比如网络音源的收包情况
+    fn status(&self) -> Option<String> {
+        None
+    }
+}
+//用cpal打开的输入设备
+pub struct CpalSource {
+    device: Device,
+    config: SupportedStreamConfig,
+    stream: Option<Stream>,
+}
+impl CpalSource {
+    //列出所有主机下的所有输入设备
+    pub fn list_devices() -> Vec<DeviceInfo> {
+        let mut list = Vec::new();
+        for host_id in cpal::available_hosts() {
+            let Ok(host) = cpal::host_from_id(host_id) else {
+                continue;
+            };
+            let default_name = host.default_input_device().and_then(|d| d.name().ok());
+            let Ok(devices) = host.input_devices() else {
+                continue;
+            };
+            for device in devices {
+                let Ok(name) = device.name() else {
+                    continue;
+                };
+                let mut info = DeviceInfo {
+                    id: DeviceId {
+                        host: host_id.name().to_string(),
+                        name: name.clone(),
+                    },
+                    is_default: default_name.as_deref() == Some(name.as_str()),
+                    channels: Vec::new(),
+                    sample_rates: Vec::new(),
+                    formats: Vec::new(),
+                };
+                if let Ok(configs) = device.supported_input_configs() {
+                    for config in configs {
+                        if !info.channels.contains(&config.channels()) {
+                            info.channels.push(config.channels());
+                        }
+                        let rate = (config.min_sample_rate().0, config.max_sample_rate().0);
+                        if !info.sample_rates.contains(&rate) {
+                            info.sample_rates.push(rate);
+                        }
+                        if !info.formats.contains(&config.sample_format()) {
+                            info.formats.push(config.sample_format());
+                        }
+                    }
+                }
+                info.channels.sort();
+                info.sample_rates.sort();
+                list.push(info);
+            }
+        }
+        list
+    }
+    //按照保存的标识找到设备 没有指定就用默认主机的默认设备
+    fn find_device(id: Option<&DeviceId>) -> Result<Device, anyhow::Error> {
+        let Some(id) = id else {
+            return cpal::default_host()
+                .default_input_device()
+                .ok_or_else(|| anyhow::anyhow!("找不到默认输入设备"));
+        };
+        for host_id in cpal::available_hosts() {
+            if host_id.name() != id.host {
+                continue;
+            }
+            let host = cpal::host_from_id(host_id)?;
+            for device in host.input_devices()? {
+                if device.name().is_ok_and(|name| name == id.name) {
+                    return Ok(device);
+                }
+            }
+        }
+        Err(anyhow::anyhow!("找不到输入设备 {}: {}", id.host, id.name))
+    }
+    //优先选f32的配置 采样率和声道数尽量和默认配置一致 实在没有就用默认配置 在回调里转换
+    fn negotiate_config(device: &Device) -> Result<SupportedStreamConfig, anyhow::Error> {
+        let default_config = device.default_input_config()?;
+        if default_config.sample_format() == SampleFormat::F32 {
+            return Ok(default_config);
+        }
+        let rate = default_config.sample_rate();
+        let f32_config = device
+            .supported_input_configs()?
+            .filter(|c| c.sample_format() == SampleFormat::F32)
+            .filter(|c| c.min_sample_rate() <= rate && rate <= c.max_sample_rate())
+            .max_by_key(|c| c.channels() == default_config.channels());
+        Ok(match f32_config {
+            Some(c) => c.with_sample_rate(rate),
+            None => default_config,
+        })
+    }
+    //找到设备并确定好配置 真正建立流要等到start
+    pub fn new(device: Option<&DeviceId>) -> Result<Self, anyhow::Error> {
+        let device = CpalSource::find_device(device)?;
+        let config = CpalSource::negotiate_config(&device)?;
+        Ok(Self {
+            device,
+            config,
+            stream: None,
+        })
+    }
+    fn create_stream(&self, tx: mpsc::Sender<Vec<f32>>) -> Result<Stream, anyhow::Error> {
+        let device = &self.device;
+        let config = &self.config;
         // let config = SupportedStreamConfig::new(
         //     default_config.channels(),
         //     SampleRate(44100), //TODO: 这里可以以后设计成可变的 但是分析了一下其实意义不大 采样率的提高不会带来频谱精度的提升 只会带来频率广度的提升 而超声波一般来说设备没有记录
         //     *default_config.buffer_size(),
         //     cpal::SampleFormat::F32,
         // );
+        //cpal 0.15 还没有暴露24位整数格式 24位的设备会以I32的形式给出
+        let stream = match config.sample_format() {
+            SampleFormat::F32 => CpalSource::build_stream::<f32>(device, config, tx)?,
+            SampleFormat::F64 => CpalSource::build_stream::<f64>(device, config, tx)?,
+            SampleFormat::I8 => CpalSource::build_stream::<i8>(device, config, tx)?,
+            SampleFormat::I16 => CpalSource::build_stream::<i16>(device, config, tx)?,
+            SampleFormat::I32 => CpalSource::build_stream::<i32>(device, config, tx)?,
+            SampleFormat::I64 => CpalSource::build_stream::<i64>(device, config, tx)?,
+            SampleFormat::U8 => CpalSource::build_stream::<u8>(device, config, tx)?,
+            SampleFormat::U16 => CpalSource::build_stream::<u16>(device, config, tx)?,
+            SampleFormat::U32 => CpalSource::build_stream::<u32>(device, config, tx)?,
+            SampleFormat::U64 => CpalSource::build_stream::<u64>(device, config, tx)?,
+            format => return Err(anyhow::anyhow!("不支持的采样格式 {format}")),
+        };
+        stream.play()?;
+        Ok(stream)
+    }
+    //按照设备的采样格式建立输入流 样本统一转换成归一化的f32
+    fn build_stream<T>(
+        device: &Device,
+        config: &SupportedStreamConfig,
+        tx: mpsc::Sender<Vec<f32>>,
+    ) -> Result<Stream, anyhow::Error>
+    where
+        T: SizedSample,
+        f32: FromSample<T>,
+    {
         let err_fn = move |err| {
             panic!("an error occurred on stream: {}", err);
         };
 
-        let channels = config.channels();
+        //这里只发送交错的原始数据 声道转换放到fetch_data里做 这样切换模式不用重建流
         let stream = device.build_input_stream(
-            &config.into(),
-            move |data: &[f32], _| {
-                //声道转换
-                let mut mono_data = Vec::with_capacity(data.len() / channels as usize);
-                for i in (0..data.len()).step_by(channels as usize) {
-                    mono_data.push((data[i] + data[i + 1]) / channels as f32);
-                }
-                tx.send(mono_data).unwrap()
+            &config.config(),
+            move |data: &[T], _| {
+                let data: Vec<f32> = data.iter().map(|s| s.to_sample::<f32>()).collect();
+                tx.send(data).unwrap()
             },
             err_fn,
             None,
         )?;
-        stream.play()?;
         Ok(stream)
     }
-    pub fn new() -> Self {
+}
+impl AudioSource for CpalSource {
+    fn start(&mut self, tx: mpsc::Sender<Vec<f32>>) -> Result<(), anyhow::Error> {
+        self.stream = Some(self.create_stream(tx)?);
+        Ok(())
+    }
+    fn stop(&mut self) {
+        if let Some(stream) = &self.stream {
+            let _ = stream.pause();
+        }
+    }
+    fn sample_rate(&self) -> u32 {
+        self.config.sample_rate().0
+    }
+    fn channels(&self) -> usize {
+        self.config.channels() as usize
+    }
+}
+impl Audio {
+    pub fn new(source: Box<dyn AudioSource>) -> Self {
         let (tx, rx) = mpsc::channel::<Vec<f32>>();
+        let mut planner = RealFftPlanner::new();
+        let fft = FftCache::new(&mut planner, 1024, 1024, FFTWindow::Hanning);
         Self {
-            stream: None,
+            source,
             rx,
             tx,
             fftsize: 1024,
+            window_len: 1024,
+            hop: 1024,
             fftwindow: FFTWindow::Hanning,
+            unit: SpectrumUnit::Dbfs,
+            channel_mode: ChannelMode::Average,
+            buffers: Vec::new(),
+            planner,
+            fft,
+            zoom: None,
+            analysis: Analysis::Fft,
+            cqt: None,
+            cqt_pending: None,
+            mel: None,
+            reassign: None,
+            multitaper: None,
         }
     }
     pub fn start(&mut self)->Result<(),anyhow::Error> {
-        self.stream = Some(Audio::create_stream(self.tx.clone())?);
-        Ok(())
+        self.source.start(self.tx.clone())
+    }
+    //当前输入的声道数
+    pub fn channels(&self) -> usize {
+        self.source.channels()
+    }
+    pub fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+    pub fn transport(&self) -> Option<Arc<Transport>> {
+        self.source.transport()
+    }
+    pub fn status(&self) -> Option<String> {
+        self.source.status()
+    }
+    pub fn set_channel_mode(&mut self, mode: ChannelMode) {
+        if self.channel_mode != mode {
+            self.channel_mode = mode;
+            //路数可能变了 之前攒的数据直接丢掉
+            self.buffers.clear();
+            if let Some(zoom) = &mut self.zoom {
+                zoom.reset();
+            }
+            self.reassign = None;
+        }
+    }
+    //按照声道模式把交错的数据拆成一路或者多路
+    fn route(&self, data: &[f32]) -> Vec<Vec<f32>> {
+        let channels = self.source.channels().max(1);
+        let frames = data.chunks_exact(channels);
+        match self.channel_mode {
+            ChannelMode::Channel(n) => {
+                let n = n.min(channels - 1);
+                vec![frames.map(|f| f[n]).collect()]
+            }
+            ChannelMode::Average => {
+                vec![frames
+                    .map(|f| f.iter().sum::<f32>() / channels as f32)
+                    .collect()]
+            }
+            //单声道没有右声道 就把左声道当成右声道 这样中间就是它本身 侧边是0
+            ChannelMode::Mid => {
+                vec![frames
+                    .map(|f| (f[0] + f[1.min(channels - 1)]) / 2.0)
+                    .collect()]
+            }
+            ChannelMode::Side => {
+                vec![frames
+                    .map(|f| (f[0] - f[1.min(channels - 1)]) / 2.0)
+                    .collect()]
+            }
+            ChannelMode::All => (0..channels)
+                .map(|c| data.chunks_exact(channels).map(|f| f[c]).collect())
+                .collect(),
+        }
+    }
+    //参数真的变了才重建 界面每帧都会调用这两个函数
+    pub fn set_fft_window_func(&mut self, window_func: FFTWindow) {
+        if self.fftwindow != window_func {
+            self.fftwindow = window_func;
+            self.fft.set_window(window_func.table(self.window_len));
+        }
+    }
+    //窗长不能比fft大小还长
+    pub fn set_fft_size(&mut self, fftsize: usize, window_len: usize) {
+        let window_len = window_len.clamp(1, fftsize);
+        if self.fftsize != fftsize || self.window_len != window_len {
+            self.fftsize = fftsize;
+            self.window_len = window_len;
+            self.fft = FftCache::new(&mut self.planner, fftsize, window_len, self.fftwindow);
+        }
+    }
+    //设置变了才重新设计滤波器
+    pub fn set_zoom(&mut self, settings: Option<ZoomSettings>) {
+        if self.zoom.as_ref().map(|z| z.settings) != settings {
+            self.zoom = settings.map(|s| Zoom::new(s, self.source.sample_rate()));
+        }
     }
-    fn fft_window(pcm_data: &mut Vec<f32>, window_func: FFTWindow) {
-        let len = pcm_data.len();
-        match window_func {
-            FFTWindow::Rectangular => {
-                // Rectangular window: no changes to pcm_data
-            }
-            FFTWindow::Hanning => {
-                for n in 0..len {
-                    let multiplier = 0.5
-                        * (1.0
-                            - (2.0 * std::f32::consts::PI * n as f32 / (len as f32 - 1.0)).cos());
-                    pcm_data[n] *= multiplier;
+    pub fn set_analysis(&mut self, analysis: Analysis) {
+        if self.analysis != analysis {
+            self.analysis = analysis;
+            let sample_rate = self.source.sample_rate();
+            //常数Q在fetch_data里开线程去算
+            self.cqt = None;
+            self.cqt_pending = None;
+            self.mel = match analysis {
+                Analysis::Mel(settings) => {
+                    Some(MelFilterbank::new(settings, sample_rate, self.fftsize))
                 }
+                _ => None,
+            };
+        }
+    }
+    //常数Q的核还没算好
+    pub fn cqt_pending(&self) -> bool {
+        self.cqt_pending.is_some()
+    }
+    //梅尔模式下每个带的中心频率
+    pub fn mel_centers(&self) -> Option<&[f32]> {
+        self.mel.as_ref().map(|m| m.centers())
+    }
+    //第bin个频点对应的频率
+    pub fn frequency_of(&self, bin: f32) -> f32 {
+        if let Some(cqt) = &self.cqt {
+            return cqt.frequency_of(bin);
+        }
+        if let Some(mel) = &self.mel {
+            return mel.frequency_of(bin);
+        }
+        match &self.zoom {
+            Some(zoom) => {
+                let half = (self.fftsize / 2) as f32;
+                zoom.settings.center + (bin - half) * zoom.output_rate() / self.fftsize as f32
             }
-            FFTWindow::Hamming => {
-                for n in 0..len {
-                    let multiplier = 0.54
-                        - 0.46 * (2.0 * std::f32::consts::PI * n as f32 / (len as f32 - 1.0)).cos();
-                    pcm_data[n] *= multiplier;
+            None => bin * self.source.sample_rate() as f32 / self.fftsize as f32,
+        }
+    }
+    //相邻两个频点差多少Hz
+    pub fn bin_width(&self) -> f32 {
+        self.frequency_of(1.0) - self.frequency_of(0.0)
+    }
+    pub fn set_spectrum_unit(&mut self, unit: SpectrumUnit) {
+        self.unit = unit
+    }
+    //相邻两帧之间前进的采样数 比窗长小就是帧之间有重叠
+    pub fn set_hop_size(&mut self, hop: usize) {
+        self.hop = hop.max(1)
+    }
+
+    fn analyze(&mut self, lane: usize, frame: &[f32]) -> Vec<f32> {
+        match &mut self.cqt {
+            Some(cqt) => cqt
+                .power_spectrum(frame, self.unit)
+                .into_iter()
+                .map(power_to_db)
+                .collect(),
+            None => {
+                let power = match &mut self.multitaper {
+                    Some(multitaper) => {
+                        let sample_rate = self.source.sample_rate() as f32;
+                        multitaper.power_spectrum(frame, self.unit, sample_rate)
+                    }
+                    None => self.do_fft(frame),
+                };
+                if let Some(reassign) = &mut self.reassign {
+                    return reassign
+                        .process(lane, frame, &self.fft.output, &power, self.unit)
+                        .into_iter()
+                        .map(power_to_db)
+                        .collect();
+                }
+                match &self.mel {
+                    Some(mel) if mel.settings.log => {
+                        mel.apply(&power).into_iter().map(power_to_db).collect()
+                    }
+                    Some(mel) => mel.apply(&power),
+                    None => power.into_iter().map(power_to_db).collect(),
                 }
             }
-            FFTWindow::Blackman => {
-                for n in 0..len {
-                    let multiplier = 0.42
-                        - 0.5 * (2.0 * std::f32::consts::PI * n as f32 / (len as f32 - 1.0)).cos()
-                        + 0.08 * (4.0 * std::f32::consts::PI * n as f32 / (len as f32 - 1.0)).cos();
-                    pcm_data[n] *= multiplier;
+        }
+    }
+    //返回校准过的线性功率谱
+    fn do_fft(&mut self, pcm_data: &[f32]) -> Vec<f32> {
+        let fft = &mut self.fft;
+        let (head, tail) = fft.input.split_at_mut(fft.window.len());
+        for ((x, s), w) in head.iter_mut().zip(pcm_data).zip(&fft.window) {
+            *x = s * w;
+        }
+        //后面补零 每次都要重新填 fft会把输入当草稿用
+        tail.fill(0.0);
+        //输入是实数 直接用实数fft 只算出单边的N/2+1个点
+        fft.plan
+            .process_with_scratch(&mut fft.input, &mut fft.output, &mut fft.scratch)
+            .unwrap();
+        //单边乘2 功率就是乘4 直流和奈奎斯特那两个点没有镜像 不乘 奇数点数的fft没有奈奎斯特点
+        let nyquist = self
+            .fftsize
+            .is_multiple_of(2)
+            .then_some(fft.output.len() - 1);
+        let power = fft
+            .output
+            .iter()
+            .enumerate()
+            .map(|(k, item)| {
+                let fold = if k == 0 || Some(k) == nyquist {
+                    1.0
+                } else {
+                    4.0
+                };
+                item.norm_sqr() * fold
+            })
+            .collect();
+        fft.calibrate(self.unit, self.source.sample_rate() as f32, power)
+    }
+    //返回每一路的频谱 和剩下还没处理的采样数
+    pub fn fetch_data(&mut self) -> Option<(Vec<Vec<f32>>, usize)> {
+        let mut blocks: Vec<Vec<f32>> = self.rx.try_iter().collect();
+        while let Some(block) = self.source.pull() {
+            blocks.push(block);
+        }
+        //采样率变了 滤波器和常数Q的核都要重新设计
+        let sample_rate = self.source.sample_rate();
+        if let Some(zoom) = &self.zoom {
+            if zoom.sample_rate != sample_rate {
+                self.zoom = Some(Zoom::new(zoom.settings, sample_rate));
+            }
+        }
+        //常数Q的核算起来很慢 放到后台线程 算好之前不出频谱
+        if let Analysis::Cqt(settings) = self.analysis {
+            if let Some(cqt) = self.cqt_pending.as_ref().and_then(|job| job.try_take()) {
+                self.cqt = Some(cqt);
+                self.cqt_pending = None;
+            }
+            let rate = match (&self.cqt_pending, &self.cqt) {
+                (Some(job), _) => Some(job.sample_rate),
+                (None, Some(cqt)) => Some(cqt.sample_rate),
+                (None, None) => None,
+            };
+            if rate != Some(sample_rate) {
+                self.cqt = None;
+                self.cqt_pending = Some(CqtJob::spawn(settings, sample_rate));
+            }
+            if self.cqt.is_none() {
+                self.buffers.clear();
+                return None;
+            }
+        }
+        if let Some(mel) = &self.mel {
+            if mel.sample_rate != sample_rate || mel.fftsize != self.fftsize {
+                self.mel = Some(MelFilterbank::new(mel.settings, sample_rate, self.fftsize));
+            }
+        }
+        //重排要用窗的导数 fft大小、窗、帧移变了都要重新算 攒着的列也一起丢掉
+        let hop = self.hop.min(self.window_len);
+        self.reassign = match self.analysis {
+            Analysis::Reassigned(mode) => match self.reassign.take() {
+                Some(r)
+                    if r.mode == mode
+                        && r.fftsize == self.fftsize
+                        && r.hop == hop
+                        && r.window == self.fft.window =>
+                {
+                    Some(r)
                 }
+                _ => Some(Reassigner::new(
+                    &mut self.planner,
+                    mode,
+                    self.fftsize,
+                    &self.fft.window,
+                    hop,
+                )),
+            },
+            _ => None,
+        };
+        self.multitaper = match self.analysis {
+            Analysis::Multitaper(settings) => match self.multitaper.take() {
+                Some(m)
+                    if m.settings == settings
+                        && m.fftsize == self.fftsize
+                        && m.window_len == self.window_len =>
+                {
+                    Some(m)
+                }
+                _ => Some(Multitaper::new(
+                    &mut self.planner,
+                    settings,
+                    self.fftsize,
+                    self.window_len,
+                )),
+            },
+            _ => None,
+        };
+        for msg in blocks {
+            let lanes = self.route(&msg);
+            if let Some(zoom) = &mut self.zoom {
+                zoom.push(&lanes);
+                continue;
+            }
+            if self.buffers.len() != lanes.len() {
+                self.buffers = vec![Vec::new(); lanes.len()];
+            }
+            for (buffer, lane) in self.buffers.iter_mut().zip(lanes) {
+                buffer.extend(lane);
             }
         }
+
+        if self.zoom.is_some() {
+            return self.fetch_zoom();
+        }
+        //每一路的长度都是一样的 看第一路就行
+        let remain = self.buffers.first()?.len();
+        let (frame_len, hop) = self.frame_and_hop();
+        if remain > frame_len {
+            //取一整帧去算 但只往前走hop个采样 剩下的留给下一帧重叠用
+            let lanes: Vec<Vec<f32>> = self
+                .buffers
+                .iter_mut()
+                .map(|buffer| {
+                    let frame = buffer[..frame_len].to_vec();
+                    buffer.drain(..hop);
+                    frame
+                })
+                .collect();
+            let spectrums = lanes
+                .iter()
+                .enumerate()
+                .map(|(i, a)| self.analyze(i, a))
+                .collect();
+            Some((spectrums, remain - hop))
+        } else {
+            None
+        }
     }
 
-    pub fn set_fft_window_func(&mut self, window_func: FFTWindow) {
-        self.fftwindow = window_func
+    //一帧多长 往前走多少 常数Q的帧长由最低频率决定 重叠比例和fft的保持一样
+    fn frame_and_hop(&self) -> (usize, usize) {
+        match &self.cqt {
+            Some(cqt) => {
+                let frame_len = cqt.frame_len();
+                let hop = self.hop * frame_len / self.window_len;
+                (frame_len, hop.clamp(1, frame_len))
+            }
+            None => (self.window_len, self.hop.min(self.window_len)),
+        }
     }
-    pub fn set_fft_size(&mut self, fftsize: usize) {
-        self.fftsize = fftsize
+    //相邻两列频谱差多少秒
+    pub fn frame_period(&self) -> f32 {
+        let (_, hop) = self.frame_and_hop();
+        match &self.zoom {
+            Some(zoom) => hop as f32 / zoom.output_rate(),
+            None => hop as f32 / self.source.sample_rate() as f32,
+        }
+    }
+    //输出的是dB还是线性功率 只有梅尔不取对数的时候是线性的
+    pub fn output_is_db(&self) -> bool {
+        self.mel.as_ref().is_none_or(|mel| mel.settings.log)
     }
 
-    fn do_fft(&self, mut pcm_data: Vec<f32>) -> Vec<f32> {
-        // Perform a forward FFT of size 1234
-        use rustfft::{num_complex::Complex, FftPlanner};
-        Audio::fft_window(&mut pcm_data, self.fftwindow);
-        let mut planner = FftPlanner::<f32>::new();
-        let fft = planner.plan_fft_forward(pcm_data.len());
-
-        // let mut buffer = vec![Complex { re: 0.0, im: 0.0 }; 1234];
-        let buffer = pcm_data
-            .into_iter()
-            .map(|item| Complex { re: item, im: 0.0 });
-        let mut b = Vec::from_iter(buffer);
-        // println!("{:?}",b);
+    //缩放模式下 抽取后的复数数据凑够一帧就做fft
+    fn fetch_zoom(&mut self) -> Option<(Vec<Vec<f32>>, usize)> {
+        let zoom = self.zoom.as_mut()?;
+        let remain = zoom.remain()?;
+        if remain <= self.window_len {
+            return None;
+        }
+        let hop = self.hop.min(self.window_len);
+        let frames = zoom.take_frames(self.window_len, hop);
+        //复数输入没有镜像 正弦只落在一边 幅度是原来的一半 所以都乘4
+        let rate = zoom.output_rate();
+        let spectrums = frames
+            .iter()
+            .map(|frame| {
+                let power = zoom
+                    .power_spectrum(frame, &self.fft.window, self.fftsize)
+                    .into_iter()
+                    .map(|p| p * 4.0)
+                    .collect();
+                self.fft
+                    .calibrate(self.unit, rate, power)
+                    .into_iter()
+                    .map(power_to_db)
+                    .collect()
+            })
+            .collect();
+        Some((spectrums, remain - hop))
+    }
 
-        fft.process(&mut b);
-        // println!("{:+.2}", b[0].re);
-        let effective = &b[0..b.len() / 2 + 1];
-        let magnitudes: Vec<f32> = effective.iter().map(|item| item.abs() * 2.0).collect(); //乘以2 因为我们取的是单边 作补偿
-                                                                                            // println!("结果{:?}",magnitudes);
-        magnitudes
+    pub fn stop(&mut self) -> () {
+        self.source.stop();
     }
-    pub fn fetch_data(&self) -> Option<(Vec<f32>, usize)> {
-        static TEMP: std::sync::RwLock<Vec<f32>> = std::sync::RwLock::new(Vec::new());
+}
 
-        let mut temp = TEMP.write().unwrap();
-        while let Ok(mut msg) = self.rx.try_recv() {
-            temp.extend(msg.drain(..));
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::VecDeque,
+        sync::{Arc, Mutex},
+    };
+
+    use super::*;
+    use crate::{
+        generator::{Generator, GeneratorSettings},
+        playback::FrameReader,
+    };
+
+    //拉取式的假音源 把准备好的数据一块一块交出去 不用声卡
+    struct FakeSource {
+        blocks: VecDeque<Vec<f32>>,
+        sample_rate: u32,
+    }
+    impl AudioSource for FakeSource {
+        fn start(&mut self, _tx: mpsc::Sender<Vec<f32>>) -> Result<(), anyhow::Error> {
+            Ok(())
+        }
+        fn stop(&mut self) {}
+        fn sample_rate(&self) -> u32 {
+            self.sample_rate
+        }
+        fn channels(&self) -> usize {
+            1
         }
+        fn pull(&mut self) -> Option<Vec<f32>> {
+            self.blocks.pop_front()
+        }
+    }
 
-        if temp.len() > self.fftsize {
-            let a: Vec<f32> = temp.drain(0..self.fftsize).collect();
-            return Some((self.do_fft(a), temp.len()));
-        } else {
-            return None;
+    //单声道的数据过一遍Audio 返回第一帧的dB频谱
+    fn spectrum_of(
+        samples: &[f32],
+        sample_rate: u32,
+        fftsize: usize,
+        window: FFTWindow,
+    ) -> Vec<f32> {
+        let source = FakeSource {
+            blocks: samples.chunks(1024).map(|c| c.to_vec()).collect(),
+            sample_rate,
+        };
+        let mut audio = Audio::new(Box::new(source));
+        audio.set_fft_window_func(window);
+        audio.set_fft_size(fftsize, fftsize);
+        audio.set_hop_size(fftsize);
+        audio.start().unwrap();
+        let (mut spectrums, _) = audio.fetch_data().expect("数据够一帧了");
+        spectrums.remove(0)
+    }
+
+    fn peak(spectrum: &[f32]) -> (usize, f32) {
+        spectrum
+            .iter()
+            .copied()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap()
+    }
+
+    fn sine(frequency: f32, amplitude: f32, sample_rate: u32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|n| {
+                let phase =
+                    std::f64::consts::TAU * frequency as f64 * n as f64 / sample_rate as f64;
+                amplitude * phase.sin() as f32
+            })
+            .collect()
+    }
+
+    //满幅正弦 换哪种窗都应该是0dB 落在它自己的频点上
+    #[test]
+    fn full_scale_sine_is_0_dbfs_for_every_window() {
+        let (rate, fftsize) = (48000, 4800);
+        let samples = sine(1000.0, 1.0, rate, fftsize * 2);
+        for window in FFTWindow::ALL {
+            let spectrum = spectrum_of(&samples, rate, fftsize, window);
+            let (bin, level) = peak(&spectrum);
+            assert_eq!(bin, 100, "{} 的峰不在1kHz上", window.name());
+            assert!(level.abs() < 0.1, "{} 读数是 {level} dB", window.name());
         }
     }
 
-    pub fn stop(&mut self) -> () {
-        self.stream.as_ref().unwrap().pause().unwrap();
+    //信号发生器默认的1kHz -20dBFS 一个频点10Hz 应该落在第100个频点上
+    #[test]
+    fn generator_tone_lands_in_right_bin_at_right_level() {
+        let settings = GeneratorSettings::default();
+        let rate = settings.sample_rate;
+        let mut generator = Generator::new(Arc::new(Mutex::new(settings)));
+        let samples = generator.read(8192).unwrap();
+        let spectrum = spectrum_of(&samples, rate, 4800, FFTWindow::Hanning);
+        let (bin, level) = peak(&spectrum);
+        assert_eq!(bin, 100);
+        assert!((level + 20.0).abs() < 0.1, "读数是 {level} dB");
     }
 }
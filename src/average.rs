@@ -0,0 +1,196 @@
+use std::collections::VecDeque;
+
+//谱平均的方式 都在线性功率上做 不在dB上做
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Averaging {
+    Off,
+    Linear(usize),    //最近N帧等权平均 和Welch法一样
+    Exponential(f32), //一阶低通 时间常数 秒
+    PeakHold(f32),    //保持最大值 每秒往下掉多少dB 0就是一直保持
+}
+
+//一路的平均状态
+struct Lane {
+    history: VecDeque<Vec<f32>>, //线性平均要的最近N帧
+    sum: Vec<f64>,               //用f64累加 不然大信号减掉以后残差会盖住底噪
+    value: Vec<f32>,             //指数平均和峰值保持的当前值
+}
+
+/*
+放在fetch_data和update_data之间 每出一列就更新一次
+输入是dB的话先换成功率再平均 平均完再换回dB
+频点数、路数、方式变了就从头开始
+*/
+pub struct Averager {
+    mode: Averaging,
+    lanes: Vec<Lane>,
+}
+impl Default for Averager {
+    fn default() -> Self {
+        Self {
+            mode: Averaging::Off,
+            lanes: Vec::new(),
+        }
+    }
+}
+impl Averager {
+    pub fn set_mode(&mut self, mode: Averaging) {
+        if self.mode != mode {
+            self.mode = mode;
+            self.reset();
+        }
+    }
+    pub fn reset(&mut self) {
+        self.lanes.clear();
+    }
+    //period是相邻两列差多少秒 db表示输入输出是dB还是线性功率
+    pub fn process(&mut self, spectrums: Vec<Vec<f32>>, period: f32, db: bool) -> Vec<Vec<f32>> {
+        if self.mode == Averaging::Off {
+            return spectrums;
+        }
+        let bins = spectrums.first().map_or(0, |s| s.len());
+        if self.lanes.len() != spectrums.len() || self.lanes.iter().any(|l| l.sum.len() != bins) {
+            self.lanes = (0..spectrums.len())
+                .map(|_| Lane {
+                    history: VecDeque::new(),
+                    sum: vec![0.0; bins],
+                    value: Vec::new(),
+                })
+                .collect();
+        }
+        let mode = self.mode;
+        spectrums
+            .into_iter()
+            .zip(&mut self.lanes)
+            .map(|(spectrum, lane)| {
+                let power: Vec<f32> = if db {
+                    spectrum.iter().map(|x| 10f32.powf(x / 10.0)).collect()
+                } else {
+                    spectrum
+                };
+                let averaged = lane.update(mode, power, period);
+                if db {
+                    averaged
+                        .into_iter()
+                        .map(|p| 10.0 * (p + 1e-20).log10())
+                        .collect()
+                } else {
+                    averaged
+                }
+            })
+            .collect()
+    }
+}
+impl Lane {
+    fn update(&mut self, mode: Averaging, power: Vec<f32>, period: f32) -> Vec<f32> {
+        match mode {
+            Averaging::Off => power,
+            Averaging::Linear(frames) => {
+                let frames = frames.max(1);
+                for (s, p) in self.sum.iter_mut().zip(&power) {
+                    *s += *p as f64;
+                }
+                self.history.push_back(power);
+                while self.history.len() > frames {
+                    let old = self.history.pop_front().unwrap();
+                    for (s, p) in self.sum.iter_mut().zip(old) {
+                        *s -= p as f64;
+                    }
+                }
+                //减来减去会有一点误差 别让它变成负的
+                let n = self.history.len() as f64;
+                self.sum.iter().map(|s| (s.max(0.0) / n) as f32).collect()
+            }
+            Averaging::Exponential(time) => {
+                if self.value.is_empty() {
+                    self.value = power;
+                } else {
+                    let alpha = 1.0 - (-period / time.max(1e-6)).exp();
+                    for (v, p) in self.value.iter_mut().zip(power) {
+                        *v += (p - *v) * alpha;
+                    }
+                }
+                self.value.clone()
+            }
+            Averaging::PeakHold(decay) => {
+                if self.value.is_empty() {
+                    self.value = power;
+                } else {
+                    let fall = 10f32.powf(-decay * period / 10.0);
+                    for (v, p) in self.value.iter_mut().zip(power) {
+                        *v = (*v * fall).max(p);
+                    }
+                }
+                self.value.clone()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //一个频点一路 线性功率进线性功率出
+    fn run(mode: Averaging, inputs: &[f32], period: f32) -> Vec<f32> {
+        let mut averager = Averager::default();
+        averager.set_mode(mode);
+        inputs
+            .iter()
+            .map(|&x| averager.process(vec![vec![x]], period, false)[0][0])
+            .collect()
+    }
+
+    fn close(a: &[f32], b: &[f32]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-5)
+    }
+
+    #[test]
+    fn off_passes_through() {
+        assert_eq!(run(Averaging::Off, &[1.0, 5.0, 2.0], 0.1), [1.0, 5.0, 2.0]);
+    }
+
+    //最近3帧的平均 不满3帧的时候按实际帧数平均
+    #[test]
+    fn linear_averages_last_frames() {
+        let out = run(Averaging::Linear(3), &[1.0, 2.0, 3.0, 4.0, 8.0], 0.1);
+        assert!(close(&out, &[1.0, 1.5, 2.0, 3.0, 5.0]), "{out:?}");
+    }
+
+    //过了一个时间常数 还剩1/e
+    #[test]
+    fn exponential_decays_by_time_constant() {
+        let out = run(Averaging::Exponential(2.0), &[1.0, 0.0, 0.0], 1.0);
+        let step = (-0.5f32).exp();
+        assert!(close(&out, &[1.0, step, step * step]), "{out:?}");
+    }
+
+    //每秒掉6dB 半秒掉3dB 新来的更大就换成新的
+    #[test]
+    fn peak_hold_falls_at_decay_rate() {
+        let out = run(Averaging::PeakHold(6.0), &[1.0, 0.0, 2.0], 0.5);
+        assert!(close(&out, &[1.0, 10f32.powf(-0.3), 2.0]), "{out:?}");
+        let out = run(Averaging::PeakHold(0.0), &[1.0, 0.0, 0.5], 0.5);
+        assert!(close(&out, &[1.0, 1.0, 1.0]), "{out:?}");
+    }
+
+    //dB的输入在功率上平均 不是直接平均dB
+    #[test]
+    fn db_input_averages_power() {
+        let mut averager = Averager::default();
+        averager.set_mode(Averaging::Linear(2));
+        averager.process(vec![vec![0.0]], 0.1, true);
+        let out = averager.process(vec![vec![-10.0]], 0.1, true)[0][0];
+        assert!((out - 10.0 * 0.55f32.log10()).abs() < 1e-4, "{out}");
+    }
+
+    //频点数变了就从头开始
+    #[test]
+    fn resets_when_shape_changes() {
+        let mut averager = Averager::default();
+        averager.set_mode(Averaging::Linear(4));
+        averager.process(vec![vec![4.0, 4.0]], 0.1, false);
+        let out = averager.process(vec![vec![1.0, 1.0, 1.0]], 0.1, false);
+        assert_eq!(out, vec![vec![1.0, 1.0, 1.0]]);
+    }
+}
@@ -2,29 +2,51 @@ use std::u8;
 
 use crate::wgpu_app::WGPUState;
 
-use egui_wgpu::wgpu::util::{BufferInitDescriptor, DeviceExt};
 use egui_wgpu::wgpu::{
-    self, BindGroupLayoutEntry, BufferBinding, BufferUsages, Queue, ShaderStages,
+    self, BindGroupLayoutEntry, BufferBinding, BufferDescriptor, BufferUsages, Queue, ShaderStages,
 };
 use egui_wgpu::wgpu::{
     include_wgsl, BindGroupDescriptor, BindGroupEntry, ComputePassDescriptor,
     ComputePipelineDescriptor, Texture, TextureDescriptor, TextureViewDescriptor,
 };
-const MAX_BUFFER_SIZE: usize = 16384; //记得和计算着色器中保持一致
-pub struct Compute {
+pub const MAX_BUFFER_SIZE: usize = 131073; //最多的频点数 也就是262144点fft
+pub const PEAK_MARKER: f32 = 1e30; //频点的值是这个的话着色器画成白色 标记峰值用 和draw.wgsl里的一样
+//每一路频谱单独一个窗格 各自有一对轮流绘制的纹理和数据缓冲区
+struct Pane {
     textures: [wgpu::Texture; 2],
     current_index: u8,
+    sample_buffer: wgpu::Buffer,
+}
+pub struct Compute {
+    panes: Vec<Pane>,
     pipeline: wgpu::ComputePipeline,
     bind_group_layout: wgpu::BindGroupLayout,
-    sample_buffer: wgpu::Buffer,
     height: u32, //宽度我们用屏幕的宽度就好 但是高度我们要的是fft的大小 所以单独指定
 }
+//缓冲区开头的参数 后面紧跟着length个频点的数据 和计算着色器中的SampleData保持一致
 #[repr(C)]
 #[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct SampleData {
-    pub data: [f32; MAX_BUFFER_SIZE],
+pub struct SampleHeader {
     pub length: u32,
-    pub factor: f32,
+    pub floor: f32,
+    pub ceiling: f32,
+    pub gamma: f32,
+}
+//颜色对应的dB范围 低于floor是最暗的颜色 高于ceiling是最亮的
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DisplayRange {
+    pub floor: f32,
+    pub ceiling: f32,
+    pub gamma: f32, //大于1压暗低电平 小于1提亮低电平
+}
+impl Default for DisplayRange {
+    fn default() -> Self {
+        Self {
+            floor: -120.0,
+            ceiling: 0.0,
+            gamma: 1.0,
+        }
+    }
 }
 impl Compute {
     fn create_texture(state: &WGPUState, label: Option<&str>, height: u32) -> Texture {
@@ -43,36 +65,55 @@ impl Compute {
             view_formats: &[],
         })
     }
-    pub fn update_data(&self, queue: &Queue, data: &[f32], factor: f32) {
-        assert!(data.len() < MAX_BUFFER_SIZE, "数据超过最大缓冲区");
-        let d = SampleData {
-            data: {
-                let mut array = [0.0; MAX_BUFFER_SIZE];
-                array[..data.len()].copy_from_slice(data);
-                array
-            },
-            length: data.len() as u32,
-            factor,
-        };
-        queue.write_buffer(&self.sample_buffer, 0, bytemuck::bytes_of(&d));
+    fn create_pane(state: &WGPUState, height: u32) -> Pane {
+        //按最大的频点数分配 一开始全是0 length是0就什么都不画
+        let sample_buffer = state.device.create_buffer(&BufferDescriptor {
+            label: Some("AudioSample Buffer"),
+            size: (size_of::<SampleHeader>() + MAX_BUFFER_SIZE * size_of::<f32>()) as u64,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Pane {
+            textures: [
+                Self::create_texture(state, Some("texture_a"), height),
+                Self::create_texture(state, Some("texture_b"), height),
+            ],
+            current_index: 0,
+            sample_buffer,
+        }
+    }
+    //每一路数据对应一个窗格 路数要和窗格数一致
+    pub fn update_data(&self, queue: &Queue, lanes: &[Vec<f32>], range: DisplayRange) {
+        assert_eq!(lanes.len(), self.panes.len(), "数据路数和窗格数不一致");
+        for (pane, data) in self.panes.iter().zip(lanes) {
+            assert!(data.len() <= MAX_BUFFER_SIZE, "数据超过最大缓冲区");
+            let d = SampleHeader {
+                length: data.len() as u32,
+                floor: range.floor,
+                ceiling: range.ceiling,
+                gamma: range.gamma,
+            };
+            //只写有用的那一段 不用每次传整个缓冲区
+            queue.write_buffer(&pane.sample_buffer, 0, bytemuck::bytes_of(&d));
+            queue.write_buffer(
+                &pane.sample_buffer,
+                size_of::<SampleHeader>() as u64,
+                bytemuck::cast_slice(data),
+            );
+        }
         queue.submit([]);
     }
+    pub fn pane_count(&self) -> usize {
+        self.panes.len()
+    }
+    //路数变了就重新建窗格 历史图像也就清掉了
+    pub fn set_pane_count(&mut self, state: &WGPUState, count: usize) {
+        self.panes = (0..count)
+            .map(|_| Self::create_pane(state, self.height))
+            .collect();
+    }
 
     pub fn new(state: &WGPUState, height: u32) -> Self {
-        //初始化缓冲区的数据
-        let data = SampleData {
-            data: [0.0; MAX_BUFFER_SIZE],
-            length: MAX_BUFFER_SIZE as u32,
-            factor: 0.15,
-        };
-
-        let texture_a = Self::create_texture(state, Some("texture_a"), height);
-        let texture_b = Self::create_texture(state, Some("texture_b"), height);
-        let sample_buffer = state.device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("AudioSample Buffer"),
-            contents: bytemuck::bytes_of(&data), //把数据填进去
-            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
-        });
         let bind_group_layout =
             state
                 .device
@@ -142,22 +183,28 @@ impl Compute {
                 cache: None,
             });
         Self {
-            textures: [texture_a, texture_b],
-            current_index: 0,
+            panes: vec![Self::create_pane(state, height)],
             pipeline: compute_pipline,
             bind_group_layout,
-            sample_buffer,
             height,
         }
     }
     pub fn on_resize(&mut self, state: &WGPUState, height: u32) {
         self.height = height;
-        self.textures[0] = Self::create_texture(state, Some("texture_a"), height);
-        self.textures[1] = Self::create_texture(state, Some("texture_b"), height);
+        for pane in &mut self.panes {
+            pane.textures[0] = Self::create_texture(state, Some("texture_a"), height);
+            pane.textures[1] = Self::create_texture(state, Some("texture_b"), height);
+        }
     }
     pub fn update(&mut self, state: &WGPUState, encoder: &mut wgpu::CommandEncoder) {
-        let current_texture = &self.textures[(self.current_index % 2) as usize];
-        let history_texture = &self.textures[(1 - self.current_index % 2) as usize];
+        for i in 0..self.panes.len() {
+            self.update_pane(state, encoder, i);
+        }
+    }
+    fn update_pane(&mut self, state: &WGPUState, encoder: &mut wgpu::CommandEncoder, index: usize) {
+        let pane = &mut self.panes[index];
+        let current_texture = &pane.textures[(pane.current_index % 2) as usize];
+        let history_texture = &pane.textures[(1 - pane.current_index % 2) as usize];
         let current_view = current_texture.create_view(&TextureViewDescriptor::default());
         let history_view = history_texture.create_view(&TextureViewDescriptor::default());
         //上面绑定组布局告诉有哪些资源 这个地方是指定实际资源的地方
@@ -175,7 +222,7 @@ impl Compute {
                 BindGroupEntry {
                     binding: 2,
                     resource: wgpu::BindingResource::Buffer(BufferBinding {
-                        buffer: &self.sample_buffer,
+                        buffer: &pane.sample_buffer,
                         offset: 0,
                         size: None,
                     }),
@@ -200,12 +247,13 @@ impl Compute {
         let dispatch_x = (state.surface_config.width + WORKGROUP_SIZE.0 - 1) / WORKGROUP_SIZE.0;
         let dispatch_y = (self.height + WORKGROUP_SIZE.1 - 1) / WORKGROUP_SIZE.1;
         compute_pass.dispatch_workgroups(dispatch_x, dispatch_y, 1);
-        self.current_index += 1;
-        self.current_index %= 2;
+        pane.current_index += 1;
+        pane.current_index %= 2;
     }
-    pub fn output(&self) -> &egui_wgpu::wgpu::Texture {
+    pub fn outputs(&self) -> impl Iterator<Item = &egui_wgpu::wgpu::Texture> {
         //返回上一次绘制完的 也就是这一次为“历史”的
-        let current_texture = &self.textures[(1 - self.current_index % 2) as usize];
-        current_texture
+        self.panes
+            .iter()
+            .map(|pane| &pane.textures[(1 - pane.current_index % 2) as usize])
     }
 }
@@ -0,0 +1,130 @@
+use std::{fs, path::PathBuf};
+
+use crate::{audio::DeviceId, compute::DisplayRange};
+
+//需要在下次启动时恢复的设置 用 key=value 的纯文本保存
+#[derive(Debug, Default)]
+pub struct Config {
+    pub input_device: Option<DeviceId>,
+    pub display_range: DisplayRange,
+}
+impl Config {
+    //配置文件放在系统的配置目录下 找不到就放在当前目录
+    fn path() -> PathBuf {
+        let dir = std::env::var_os("APPDATA")
+            .or_else(|| std::env::var_os("XDG_CONFIG_HOME"))
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .unwrap_or_default();
+        dir.join("spectrum_monitor").join("config.txt")
+    }
+    pub fn load() -> Self {
+        match fs::read_to_string(Self::path()) {
+            Ok(text) => Self::parse(&text),
+            Err(_) => Self::default(),
+        }
+    }
+    fn parse(text: &str) -> Self {
+        let mut config = Self::default();
+        let mut host = None;
+        let mut name = None;
+        let range = &mut config.display_range;
+        for line in text.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            match key.trim() {
+                "input_host" => host = Some(value.trim().to_string()),
+                "input_device" => name = Some(value.trim().to_string()),
+                "display_floor" => range.floor = value.trim().parse().unwrap_or(range.floor),
+                "display_ceiling" => range.ceiling = value.trim().parse().unwrap_or(range.ceiling),
+                "display_gamma" => range.gamma = value.trim().parse().unwrap_or(range.gamma),
+                _ => {}
+            }
+        }
+        if let (Some(host), Some(name)) = (host, name) {
+            config.input_device = Some(DeviceId { host, name });
+        }
+        //手改坏了的值会让着色器算出乱七八糟的颜色 和界面一样限制住 不合理的换回默认
+        let default = DisplayRange::default();
+        if !(range.floor.is_finite() && range.ceiling.is_finite())
+            || range.ceiling < range.floor + 1.0
+        {
+            range.floor = default.floor;
+            range.ceiling = default.ceiling;
+        }
+        range.gamma = if range.gamma > 0.0 {
+            range.gamma.clamp(0.2, 5.0)
+        } else {
+            default.gamma
+        };
+        config
+    }
+    pub fn save(&self) -> Result<(), anyhow::Error> {
+        let path = Self::path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut text = String::new();
+        if let Some(device) = &self.input_device {
+            text.push_str(&format!("input_host={}\n", device.host));
+            text.push_str(&format!("input_device={}\n", device.name));
+        }
+        let range = &self.display_range;
+        text.push_str(&format!("display_floor={}\n", range.floor));
+        text.push_str(&format!("display_ceiling={}\n", range.ceiling));
+        text.push_str(&format!("display_gamma={}\n", range.gamma));
+        fs::write(path, text)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_saved_values() {
+        let config = Config::parse(
+            "input_host=ALSA\ninput_device=hw:1\ndisplay_floor=-90\ndisplay_ceiling=-10\ndisplay_gamma=2\n",
+        );
+        let device = config.input_device.unwrap();
+        assert_eq!(
+            (device.host.as_str(), device.name.as_str()),
+            ("ALSA", "hw:1")
+        );
+        let range = config.display_range;
+        assert_eq!(
+            (range.floor, range.ceiling, range.gamma),
+            (-90.0, -10.0, 2.0)
+        );
+    }
+
+    //手改坏了的显示范围换回默认 伽马限制在界面的范围里
+    #[test]
+    fn rejects_bad_display_range() {
+        let default = DisplayRange::default();
+        for text in [
+            "display_floor=0\ndisplay_ceiling=0\n",
+            "display_floor=-10\ndisplay_ceiling=-20\n",
+            "display_floor=NaN\n",
+            "display_ceiling=inf\n",
+        ] {
+            let range = Config::parse(text).display_range;
+            assert_eq!(
+                (range.floor, range.ceiling),
+                (default.floor, default.ceiling),
+                "{text}"
+            );
+        }
+        for (text, gamma) in [
+            ("display_gamma=0", default.gamma),
+            ("display_gamma=-1", default.gamma),
+            ("display_gamma=NaN", default.gamma),
+            ("display_gamma=0.01", 0.2),
+            ("display_gamma=100", 5.0),
+        ] {
+            assert_eq!(Config::parse(text).display_range.gamma, gamma, "{text}");
+        }
+    }
+}
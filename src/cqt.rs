@@ -0,0 +1,256 @@
+use std::{
+    f64::consts::TAU,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+};
+
+use realfft::{num_complex::Complex, RealFftPlanner, RealToComplex};
+
+use crate::audio::SpectrumUnit;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CqtSettings {
+    pub bins_per_octave: u32,
+    pub min_freq: f32,
+    pub max_freq: f32,
+}
+impl Default for CqtSettings {
+    fn default() -> Self {
+        Self {
+            bins_per_octave: 24,
+            min_freq: 55.0,
+            max_freq: 16000.0,
+        }
+    }
+}
+
+//一个频点的频域核 只留下不接近0的那一段
+struct Kernel {
+    start: usize,
+    values: Vec<Complex<f32>>, //已经取过共轭了
+    enbw: f32,                 //这个频点的等效噪声带宽 Hz
+}
+
+/*
+长度为n的对称汉宁窗乘上角频率omega的复正弦 放在帧里offset的位置 补零到len点做fft以后第j个点的值
+汉宁窗是0.5-0.5cos(αn) α=2π/(n-1) 拆成三个复正弦 每一个的和都是狄利克雷核 直接有公式
+*/
+fn kernel_bin(n: usize, omega: f64, offset: usize, len: usize, j: usize) -> Complex<f64> {
+    let n_f = n as f64;
+    //Σe^(-ixk) k从0到n-1
+    let dirichlet = |x: f64| {
+        let half = (x / 2.0).sin();
+        let magnitude = if half.abs() < 1e-12 {
+            n_f
+        } else {
+            (x * n_f / 2.0).sin() / half
+        };
+        Complex::from_polar(magnitude, -x * (n_f - 1.0) / 2.0)
+    };
+    let theta = TAU * j as f64 / len as f64;
+    let x = theta - omega;
+    let alpha = TAU / (n_f - 1.0);
+    let sum = dirichlet(x) * 0.5 - (dirichlet(x - alpha) + dirichlet(x + alpha)) * 0.25;
+    sum * Complex::from_polar(1.0, -theta * offset as f64)
+}
+
+/*
+常数Q变换 Brown和Puckette的做法
+每个频点的时域核是长度N_k=Q·fs/f_k的汉宁窗乘复正弦 频率越低核越长 每个频点的带宽和频率成正比
+所有的核放在同一个帧的正中间 先做一次fft 再和每个核的频谱做内积 核的频谱大部分接近0 只存不为0的那一段
+*/
+pub struct Cqt {
+    pub settings: CqtSettings,
+    pub sample_rate: u32,
+    plan: Arc<dyn RealToComplex<f32>>,
+    input: Vec<f32>,
+    output: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+    kernels: Vec<Kernel>,
+}
+impl Cqt {
+    //cancel被置上就不算了 返回None
+    fn new(settings: CqtSettings, sample_rate: u32, cancel: &AtomicBool) -> Option<Self> {
+        let fs = sample_rate as f64;
+        let b = settings.bins_per_octave.max(1) as f64;
+        let q = 1.0 / (2f64.powf(1.0 / b) - 1.0);
+        let min_freq = (settings.min_freq as f64).max(1.0);
+        let max_freq = (settings.max_freq as f64).min(fs / 2.0);
+        let mut freqs: Vec<f64> = (0..)
+            .map(|k| min_freq * 2f64.powf(k as f64 / b))
+            .take_while(|f| *f <= max_freq)
+            .collect();
+        //上限比下限还低的时候至少留一个频点 不然纹理高度是0
+        if freqs.is_empty() {
+            freqs.push(min_freq);
+        }
+        //最低的那个频点的核最长 帧长就按它来
+        let len = ((q * fs / min_freq).ceil() as usize).max(4);
+        let len = len + len % 2;
+
+        let mut kernels = Vec::with_capacity(freqs.len());
+        for &f in &freqs {
+            if cancel.load(Ordering::Relaxed) {
+                return None;
+            }
+            let n_k = ((q * fs / f).ceil() as usize).clamp(4, len);
+            //对称汉宁窗的Σw和Σw² 按Σw归一化 这样幅度为A的正弦内积出来是A/2
+            let sum = (n_k - 1) as f64 / 2.0;
+            let power = 3.0 * (n_k - 1) as f64 / 8.0;
+            //主瓣是正负2个窗长的频点 再往外16个就已经比峰低60dB以上了
+            let center = f * len as f64 / fs;
+            let spread = 16.0 * len as f64 / n_k as f64;
+            let first = (center - spread).floor().max(0.0) as usize;
+            let last = ((center + spread).ceil() as usize).min(len / 2);
+            let offset = (len - n_k) / 2;
+            //内积在频域做 要除以帧长 顺便取共轭
+            let values: Vec<Complex<f64>> = (first..=last)
+                .map(|j| (kernel_bin(n_k, TAU * f / fs, offset, len, j) / sum).conj() / len as f64)
+                .collect();
+            let max = values.iter().map(|x| x.norm()).fold(0.0, f64::max);
+            let keep = |x: &Complex<f64>| x.norm() > max * 1e-3;
+            let start = values.iter().position(keep).unwrap_or(0);
+            let end = values.iter().rposition(keep).map_or(start, |e| e + 1);
+            kernels.push(Kernel {
+                start: first + start,
+                values: values[start..end]
+                    .iter()
+                    .map(|x| Complex::new(x.re as f32, x.im as f32))
+                    .collect(),
+                enbw: (fs * power / (sum * sum)) as f32,
+            });
+        }
+
+        let plan = RealFftPlanner::new().plan_fft_forward(len);
+        Some(Self {
+            settings,
+            sample_rate,
+            input: plan.make_input_vec(),
+            output: plan.make_output_vec(),
+            scratch: plan.make_scratch_vec(),
+            plan,
+            kernels,
+        })
+    }
+    //一帧要多少个采样
+    pub fn frame_len(&self) -> usize {
+        self.input.len()
+    }
+    //第bin个频点的中心频率
+    pub fn frequency_of(&self, bin: f32) -> f32 {
+        self.settings.min_freq.max(1.0)
+            * 2f32.powf(bin / self.settings.bins_per_octave.max(1) as f32)
+    }
+    //每个频点的幅度平方或者功率谱密度 满幅正弦是1 和fft的结果一样换算成dB
+    pub fn power_spectrum(&mut self, frame: &[f32], unit: SpectrumUnit) -> Vec<f32> {
+        let len = self.input.len();
+        self.input.copy_from_slice(&frame[..len]);
+        self.plan
+            .process_with_scratch(&mut self.input, &mut self.output, &mut self.scratch)
+            .unwrap();
+        let spectrum = &self.output;
+        self.kernels
+            .iter()
+            .map(|kernel| {
+                let c = spectrum[kernel.start..]
+                    .iter()
+                    .zip(&kernel.values)
+                    .fold(Complex::new(0.0, 0.0), |acc, (x, k)| acc + x * k);
+                //内积是A/2 平方再乘4就是A²
+                let power = c.norm_sqr() * 4.0;
+                match unit {
+                    SpectrumUnit::Dbfs => power,
+                    SpectrumUnit::Psd => power / kernel.enbw,
+                }
+            })
+            .collect()
+    }
+}
+
+//在后台线程里算核 被换掉或者丢掉的时候通知线程别算了
+pub struct CqtJob {
+    pub sample_rate: u32,
+    cancel: Arc<AtomicBool>,
+    rx: mpsc::Receiver<Cqt>,
+}
+impl CqtJob {
+    pub fn spawn(settings: CqtSettings, sample_rate: u32) -> Self {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = mpsc::channel();
+        let flag = cancel.clone();
+        std::thread::spawn(move || {
+            if let Some(cqt) = Cqt::new(settings, sample_rate, &flag) {
+                let _ = tx.send(cqt);
+            }
+        });
+        Self {
+            sample_rate,
+            cancel,
+            rx,
+        }
+    }
+    //算好了就拿走 没算好是None
+    pub fn try_take(&self) -> Option<Cqt> {
+        self.rx.try_recv().ok()
+    }
+}
+impl Drop for CqtJob {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustfft::FftPlanner;
+
+    use super::*;
+    use crate::window::FFTWindow;
+
+    //公式算出来的核和直接把时域的核做fft是一样的
+    #[test]
+    fn kernel_matches_direct_fft() {
+        let (len, n, offset) = (1000, 301, 349);
+        let omega = TAU * 0.0731;
+        let window = FFTWindow::Hanning.table(n);
+        let mut buffer: Vec<Complex<f64>> = vec![Complex::new(0.0, 0.0); len];
+        for (k, w) in window.iter().enumerate() {
+            buffer[offset + k] = Complex::from_polar(*w as f64, omega * k as f64);
+        }
+        FftPlanner::<f64>::new()
+            .plan_fft_forward(len)
+            .process(&mut buffer);
+        for (j, x) in buffer.iter().enumerate().take(len / 2 + 1) {
+            let y = kernel_bin(n, omega, offset, len, j);
+            //窗表是f32的 差一点点
+            assert!((x - y).norm() < 1e-4, "第{j}个点 {x} 和 {y} 不一样");
+        }
+    }
+
+    //满幅正弦在它自己那个频点上是0dB
+    #[test]
+    fn full_scale_sine_is_0_db_at_its_bin() {
+        let settings = CqtSettings {
+            bins_per_octave: 12,
+            min_freq: 110.0,
+            max_freq: 4000.0,
+        };
+        let mut cqt = Cqt::new(settings, 8000, &AtomicBool::new(false)).unwrap();
+        let frequency = cqt.frequency_of(24.0);
+        let frame: Vec<f32> = (0..cqt.frame_len())
+            .map(|n| (TAU * frequency as f64 * n as f64 / 8000.0).sin() as f32)
+            .collect();
+        let power = cqt.power_spectrum(&frame, SpectrumUnit::Dbfs);
+        let level = 10.0 * power[24].log10();
+        assert!(level.abs() < 0.1, "读数是 {level} dB");
+    }
+
+    //被取消的不会算完
+    #[test]
+    fn cancelled_build_stops() {
+        let cancel = AtomicBool::new(true);
+        assert!(Cqt::new(CqtSettings::default(), 48000, &cancel).is_none());
+    }
+}
@@ -0,0 +1,150 @@
+use std::{fs::File, path::Path};
+
+use symphonia::core::{
+    audio::SampleBuffer,
+    codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL},
+    errors::Error,
+    formats::{FormatOptions, FormatReader, SeekMode, SeekTo},
+    io::MediaSourceStream,
+    meta::MetadataOptions,
+    probe::Hint,
+    units::{Time, TimeBase},
+};
+
+use crate::playback::FrameReader;
+
+//用symphonia解码的压缩音频 flac、mp3、ogg vorbis、aac之类的
+pub struct DecodedFile {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    time_base: Option<TimeBase>,
+    sample_rate: u32,
+    channels: usize,
+    length: Option<u64>,
+    pending: Vec<f32>, //解码出来但还没被读走的交错数据
+    skip: usize,       //跳转之后要丢掉的帧数 精确跳转时落点在目标之前
+}
+impl DecodedFile {
+    pub fn open(path: &Path) -> Result<Self, anyhow::Error> {
+        let mss = MediaSourceStream::new(Box::new(File::open(path)?), Default::default());
+        let mut hint = Hint::new();
+        if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+            hint.with_extension(ext);
+        }
+        let probed = symphonia::default::get_probe().format(
+            &hint,
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )?;
+        let format = probed.format;
+        let track = format
+            .tracks()
+            .iter()
+            .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or_else(|| anyhow::anyhow!("文件里没有音频轨道"))?;
+        let params = track.codec_params.clone();
+        let decoder = symphonia::default::get_codecs().make(&params, &DecoderOptions::default())?;
+        let mut file = Self {
+            track_id: track.id,
+            time_base: params.time_base,
+            sample_rate: params.sample_rate.unwrap_or(0),
+            channels: params.channels.map_or(0, |c| c.count()),
+            length: params.n_frames,
+            format,
+            decoder,
+            pending: Vec::new(),
+            skip: 0,
+        };
+        //有的容器不在头里写采样率和声道 先解一个包看看
+        if file.sample_rate == 0 || file.channels == 0 {
+            file.decode_packet()?;
+        }
+        if file.sample_rate == 0 || file.channels == 0 {
+            return Err(anyhow::anyhow!("无法确定采样率和声道数"));
+        }
+        Ok(file)
+    }
+    //解一个包放到pending里 文件结束返回false
+    fn decode_packet(&mut self) -> Result<bool, anyhow::Error> {
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(Error::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    return Ok(false)
+                }
+                Err(Error::ResetRequired) => {
+                    self.decoder.reset();
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+            let decoded = match self.decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                //坏掉的包跳过就好
+                Err(Error::DecodeError(_)) => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let spec = *decoded.spec();
+            self.sample_rate = spec.rate;
+            self.channels = spec.channels.count();
+            let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+            buffer.copy_interleaved_ref(decoded);
+            let mut samples = buffer.samples();
+            if self.skip > 0 {
+                let skip = (self.skip * self.channels).min(samples.len());
+                samples = &samples[skip..];
+                self.skip -= skip / self.channels;
+            }
+            self.pending.extend_from_slice(samples);
+            return Ok(true);
+        }
+    }
+}
+impl FrameReader for DecodedFile {
+    fn read(&mut self, frames: usize) -> Result<Vec<f32>, anyhow::Error> {
+        let count = frames * self.channels;
+        while self.pending.len() < count {
+            if !self.decode_packet()? {
+                break;
+            }
+        }
+        let count = count.min(self.pending.len());
+        Ok(self.pending.drain(..count).collect())
+    }
+    fn seek(&mut self, frame: u64) -> Result<(), anyhow::Error> {
+        let seconds = frame as f64 / self.sample_rate as f64;
+        let seeked = self.format.seek(
+            SeekMode::Accurate,
+            SeekTo::Time {
+                time: Time::from(seconds),
+                track_id: Some(self.track_id),
+            },
+        )?;
+        self.decoder.reset();
+        self.pending.clear();
+        //落点和目标之间差的部分解码之后丢掉
+        let gap = seeked.required_ts.saturating_sub(seeked.actual_ts);
+        self.skip = match self.time_base {
+            Some(tb) => {
+                let time = tb.calc_time(gap);
+                ((time.seconds as f64 + time.frac) * self.sample_rate as f64) as usize
+            }
+            None => gap as usize,
+        };
+        Ok(())
+    }
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+    fn channels(&self) -> usize {
+        self.channels
+    }
+    fn length(&self) -> Option<u64> {
+        self.length
+    }
+}
@@ -2,12 +2,16 @@
 @group(0) @binding(0) var history_tex: texture_2d<f32>;
 @group(0) @binding(1) var current_tex: texture_storage_2d<rgba8unorm, write>;
 struct SampleData {
-    data: array<f32, 16384>,
     length: u32,
-    factor: f32,
+    floor: f32,
+    ceiling: f32,
+    gamma: f32,
+    data: array<f32>,
 };
 @group(0) @binding(2)
 var<storage,read> sampleData: SampleData;
+// 峰值标记 值大于等于这个的频点直接画成白色 和compute.rs里的一样
+const PEAK_MARKER: f32 = 1e30;
 fn hsv2rgb(h: f32, s: f32, v: f32) -> vec3f {
 
     if s <= 0.0 {
@@ -36,8 +40,10 @@ fn hsv2rgb(h: f32, s: f32, v: f32) -> vec3f {
     }
 }
 fn qwq(g: f32) -> vec3f {
-    // 使用指数压缩数据到0-1范围，参数0.15控制压缩程度
-    let gray = 1.0 - exp(-1 * sampleData.factor * g);
+    // 数据是dBFS，把floor到ceiling之间线性映射到0-1，再用gamma调整
+    let range = max(sampleData.ceiling - sampleData.floor, 0.001);
+    let level = clamp((g - sampleData.floor) / range, 0.0, 1.0);
+    let gray = pow(level, sampleData.gamma);
     
     // 调整色相范围从蓝色(0.6)到红色(0.0)
     let hue = 0.6 * (1.0 - gray);
@@ -50,6 +56,23 @@ fn qwq(g: f32) -> vec3f {
 
     return hsv2rgb(hue, saturation, value);
 }
+// 取第y行对应的值 纹理的行数比频点少的时候一行覆盖好几个频点 取其中最大的 免得窄的峰被漏掉
+fn level_at(y: u32, height: u32) -> f32 {
+    let length = sampleData.length;
+    if length == 0u {
+        return -1000.0;
+    }
+    if height >= length {
+        return sampleData.data[min(y, length - 1u)];
+    }
+    let start = y * length / height;
+    let end = max((y + 1u) * length / height, start + 1u);
+    var m = sampleData.data[start];
+    for (var i = start + 1u; i < end; i++) {
+        m = max(m, sampleData.data[i]);
+    }
+    return m;
+}
 @compute @workgroup_size(32,8)
 
 
@@ -63,7 +86,12 @@ fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
 
     // 如果目标在右侧新列（绘制新频谱条）
     if dst_pixel.x == textureDimensions(history_tex).x - 1 {
-        textureStore(current_tex, dst_pixel, vec4(qwq(sampleData.data[dst_pixel.y]), 1.0));
+        let g = level_at(dst_pixel.y, textureDimensions(current_tex).y);
+        if g >= PEAK_MARKER {
+            textureStore(current_tex, dst_pixel, vec4(1.0, 1.0, 1.0, 1.0));
+        } else {
+            textureStore(current_tex, dst_pixel, vec4(qwq(g), 1.0));
+        }
         // textureStore(current_tex, dst_pixel, vec4(1.0,0.0,0.0, 1.0));
     } else {
         // 从历史纹理的右侧一列采样（实现左移）
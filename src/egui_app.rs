@@ -1,12 +1,75 @@
 use crate::{
-    audio::{self, FFTWindow},
+    args::Args,
+    average::{Averager, Averaging},
+    compute::{DisplayRange, MAX_BUFFER_SIZE, PEAK_MARKER},
+    audio::{
+        self, Analysis, AudioSource, ChannelMode, CpalSource, DeviceId, DeviceInfo, SpectrumUnit,
+    },
+    config::Config,
+    cqt::CqtSettings,
+    mel::{MelExport, MelScale, MelSettings},
+    multitaper::MultitaperSettings,
+    peaks::{find_peaks, Interpolation, Peak, PeakSettings},
+    reassign::ReassignMode,
+    traces::{decimate, SpectrumTraces},
+    decoder::DecodedFile,
+    generator::{Generator, GeneratorSettings, Waveform},
+    network::{NetworkSource, Protocol},
+    pcm::RawFormat,
+    pipe::{PipeInput, PipeSource},
+    playback::{FrameReader, PlaybackSource},
+    wav::WavFile,
     wgpu_app::WGPUState,
+    window::FFTWindow,
+    zoom::ZoomSettings,
 };
 use audio::Audio;
 use egui::{viewport, Color32, Context, Frame, Margin, Rounding};
+use egui_plot::{Legend, Line, Plot};
 use egui_wgpu::Renderer;
 use egui_winit::State;
 use frame_counter::FrameCounter;
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+//频点数不能超过计算着色器的缓冲区
+const MAX_FFT_SIZE: u32 = (MAX_BUFFER_SIZE as u32 - 1) * 2;
+
+//界面上选的平均方式 参数分开存
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AverageMode {
+    Off,
+    Linear,
+    Exponential,
+    PeakHold,
+}
+
+//界面上选的分析方式 参数分开存 切换回来的时候还在
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AnalysisMode {
+    Fft,
+    Cqt,
+    Mel,
+    Reassigned,
+    Multitaper,
+}
+
+//音源的种类
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SourceKind {
+    Device,
+    File,
+    Generator,
+    Pipe,
+    Network,
+}
+
+//拖完了、输入框失去焦点或者点一下就改好的 才算改完 慢的设置等这时候再生效
+fn committed(r: &egui::Response) -> bool {
+    r.drag_stopped() || r.lost_focus() || (r.changed() && !r.dragged())
+}
 
 pub struct EguiApp {
     render: Renderer,
@@ -16,9 +79,56 @@ pub struct EguiApp {
     buffer_remain: usize,
     select_fftwindow: FFTWindow,
     fftsize: u32,
-    value_gain_factor: f32,
+    zero_pad: bool, //窗长单独设置 不够fft大小的部分补零
+    window_len: u32,
+    overlap: f32, //相邻两帧的重叠比例 0到1
+    spectrum_unit: SpectrumUnit,
+    zoom_enabled: bool,
+    zoom: ZoomSettings,
+    analysis_mode: AnalysisMode,
+    cqt: CqtSettings,
+    cqt_applied: CqtSettings, //真正传给Audio的
+    mel: MelSettings,
+    mel_export_path: String,
+    mel_export: Option<MelExport>,
+    mel_export_time: f64, //导出的下一行对应的秒数
+    reassign_mode: ReassignMode,
+    multitaper: MultitaperSettings,
+    multitaper_applied: MultitaperSettings, //真正传给Audio的
+    average_mode: AverageMode,
+    average_frames: usize,
+    average_time: f32, //指数平均的时间常数 秒
+    peak_decay: f32, //峰值保持每秒掉多少dB
+    averager: Averager,
+    show_traces: bool,
+    trace_current: bool,
+    trace_average: bool,
+    trace_max: bool,
+    trace_min: bool,
+    traces: SpectrumTraces,
+    peaks_enabled: bool,
+    peak_markers: bool, //在瀑布图上标出来
+    peak_settings: PeakSettings,
+    peaks: Vec<Vec<Peak>>, //每一路最新的一列里找到的峰
+    display_range: DisplayRange,
     pub log_scale: f32,
-    fail:Option<String>
+    fail:Option<String>,
+    config: Config,
+    devices: Vec<DeviceInfo>,
+    select_device: Option<DeviceId>,
+    select_channel_mode: ChannelMode,
+    source_kind: SourceKind,
+    file_path: String,
+    generator: Arc<Mutex<GeneratorSettings>>,
+    tones_text: String, //多音的频率列表 逗号分隔
+    pipe_input: PipeInput,
+    pipe_path: String,
+    pipe_format: RawFormat,
+    pipe_rate: u32,
+    pipe_channels: usize,
+    pipe_running: Option<(PipeInput, RawFormat, u32, usize)>, //现在的音源是用哪组管道设置建的
+    net_protocol: Protocol,
+    net_port: u16,
 }
 impl EguiApp {
     pub fn new(
@@ -27,6 +137,7 @@ impl EguiApp {
         output_color_format: egui_wgpu::wgpu::TextureFormat,
         output_depth_format: Option<egui_wgpu::wgpu::TextureFormat>,
         msaa_samples: u32,
+        args: &Args,
     ) -> Self {
         let egui_ctx = Context::default();
         //设置中文字体
@@ -63,7 +174,15 @@ impl EguiApp {
             true,
         );
 
-        Self {
+        //恢复上次选择的输入设备 如果设备已经不在了就用默认设备
+        let config = Config::load();
+        let devices = CpalSource::list_devices();
+        let select_device = config
+            .input_device
+            .clone()
+            .filter(|id| devices.iter().any(|d| &d.id == id));
+
+        let mut app = Self {
             state: egui_state,
             render: egui_render,
             audio_stream: None,
@@ -71,10 +190,81 @@ impl EguiApp {
             buffer_remain: 0,
             select_fftwindow: FFTWindow::Hanning,
             fftsize: 1024,
-            value_gain_factor: 0.15,
+            zero_pad: false,
+            window_len: 1024,
+            overlap: 0.0,
+            spectrum_unit: SpectrumUnit::Dbfs,
+            zoom_enabled: false,
+            zoom: ZoomSettings {
+                center: 1000.0,
+                decimation: 64,
+            },
+            analysis_mode: AnalysisMode::Fft,
+            cqt: CqtSettings::default(),
+            cqt_applied: CqtSettings::default(),
+            mel: MelSettings::default(),
+            mel_export_path: "mel.csv".to_string(),
+            mel_export: None,
+            mel_export_time: 0.0,
+            reassign_mode: ReassignMode::Synchrosqueeze,
+            multitaper: MultitaperSettings::default(),
+            multitaper_applied: MultitaperSettings::default(),
+            average_mode: AverageMode::Off,
+            average_frames: 16,
+            average_time: 1.0,
+            peak_decay: 20.0,
+            averager: Averager::default(),
+            show_traces: false,
+            trace_current: true,
+            trace_average: false,
+            trace_max: true,
+            trace_min: false,
+            traces: SpectrumTraces::default(),
+            peaks_enabled: false,
+            peak_markers: false,
+            peak_settings: PeakSettings::default(),
+            peaks: Vec::new(),
+            display_range: config.display_range,
             log_scale: 0.5,
-            fail:None
+            fail:None,
+            config,
+            devices,
+            select_device,
+            select_channel_mode: ChannelMode::Average,
+            source_kind: SourceKind::Device,
+            file_path: String::new(),
+            generator: Arc::new(Mutex::new(GeneratorSettings::default())),
+            tones_text: "440, 1000, 5000".to_string(),
+            pipe_input: PipeInput::Stdin,
+            pipe_path: String::new(),
+            pipe_format: args.format,
+            pipe_rate: args.rate,
+            pipe_channels: args.channels,
+            pipe_running: None,
+            net_protocol: Protocol::Udp,
+            net_port: 9000,
+        };
+        //命令行指定了文件就直接开始播放
+        if let Some(file) = &args.file {
+            app.source_kind = SourceKind::File;
+            app.file_path = file.display().to_string();
+            app.start_audio();
         }
+        if let Some(pipe) = &args.pipe {
+            app.source_kind = SourceKind::Pipe;
+            if let PipeInput::Fifo(path) = pipe {
+                app.pipe_path = path.display().to_string();
+            }
+            app.pipe_input = pipe.clone();
+            app.start_audio();
+        }
+        if let Some((protocol, port)) = args.network {
+            app.source_kind = SourceKind::Network;
+            app.net_protocol = protocol;
+            app.net_port = port;
+            app.start_audio();
+        }
+        app
     }
     pub fn on_input_event(
         &mut self,
@@ -89,6 +279,7 @@ impl EguiApp {
         self.state.egui_ctx().begin_pass(raw_input);
     }
     fn draw(&mut self) {
+        let ctx = self.state.egui_ctx().clone();
         egui::Window::new("频谱监视器选项")
             .resizable(true)
             .vscroll(true)
@@ -101,7 +292,7 @@ impl EguiApp {
             )
             .default_height(200.0)
             .default_open(true)
-            .show(self.state.egui_ctx(), |ui| {
+            .show(&ctx, |ui| {
                 ui.vertical_centered(|ui| {
                     if let Some(fail) = &self.fail{
                         ui.code(fail).highlight();
@@ -116,70 +307,970 @@ impl EguiApp {
                         egui::Frame::default()
                             .show(ui, |ui| {
                                 if ui.button("开始").clicked() {
-                                    self.audio_stream = Some(Audio::new());
-                                    if let Err(e) = self.audio_stream.as_mut().unwrap().start(){
-                                        self.fail=Some(e.to_string());
-                                    }
+                                    self.start_audio();
                                 }
                                 if ui.button("暂停").clicked() {
                                     self.audio_stream.as_mut().unwrap().stop();
                                 }
                             });
                         ui.end_row();
-                        ui.label("FFT 大小");
-                        ui.add(
-                            egui::Slider::new(&mut self.fftsize, 32..=4096 * 4).logarithmic(true),
-                        );
-                        ui.end_row();
-                        ui.label("值增益系数");
-                        ui.add(
-                            egui::Slider::new(&mut self.value_gain_factor, 0.1..=0.9)
-                                .logarithmic(true),
-                        );
-                        ui.end_row();
-                        ui.label("对数坐标系数");
-                        ui.add(
-                            egui::Slider::new(&mut self.log_scale, 0.00000005..=1.0)
-                                .logarithmic(true),
-                        );
-                        ui.end_row();
-                        ui.label("FFT 窗函数");
-                        egui::ComboBox::from_label("")
-                            .selected_text(format!("{:?}", self.select_fftwindow))
+                        self.draw_source_options(ui);
+                        ui.label("声道");
+                        //还没开始的时候按选中设备支持的最大声道数来列
+                        let channels = match &self.audio_stream {
+                            Some(a) => a.channels(),
+                            None => self
+                                .devices
+                                .iter()
+                                .find(|d| Some(&d.id) == self.select_device.as_ref())
+                                .and_then(|d| d.channels.iter().max())
+                                .map_or(2, |c| *c as usize),
+                        };
+                        egui::ComboBox::from_id_salt("channel_mode")
+                            .selected_text(match self.select_channel_mode {
+                                ChannelMode::Channel(n) => format!("声道 {}", n + 1),
+                                ChannelMode::Average => "平均".to_string(),
+                                ChannelMode::Mid => "中 (L+R)".to_string(),
+                                ChannelMode::Side => "侧 (L-R)".to_string(),
+                                ChannelMode::All => "全部分开".to_string(),
+                            })
                             .show_ui(ui, |ui| {
                                 ui.selectable_value(
-                                    &mut self.select_fftwindow,
-                                    FFTWindow::Hanning,
-                                    "Hanning",
+                                    &mut self.select_channel_mode,
+                                    ChannelMode::Average,
+                                    "平均",
                                 );
                                 ui.selectable_value(
-                                    &mut self.select_fftwindow,
-                                    FFTWindow::Rectangular,
-                                    "Rectangular",
+                                    &mut self.select_channel_mode,
+                                    ChannelMode::Mid,
+                                    "中 (L+R)",
                                 );
                                 ui.selectable_value(
-                                    &mut self.select_fftwindow,
-                                    FFTWindow::Hamming,
-                                    "Hamming",
+                                    &mut self.select_channel_mode,
+                                    ChannelMode::Side,
+                                    "侧 (L-R)",
                                 );
                                 ui.selectable_value(
-                                    &mut self.select_fftwindow,
-                                    FFTWindow::Blackman,
-                                    "Blackman",
+                                    &mut self.select_channel_mode,
+                                    ChannelMode::All,
+                                    "全部分开",
                                 );
+                                for n in 0..channels {
+                                    ui.selectable_value(
+                                        &mut self.select_channel_mode,
+                                        ChannelMode::Channel(n),
+                                        format!("声道 {}", n + 1),
+                                    );
+                                }
                             });
                         ui.end_row();
+                        self.draw_analysis_options(ui);
+                        self.draw_overlap_options(ui);
+                        self.draw_average_options(ui);
+                        ui.label("频谱单位");
+                        ui.horizontal(|ui| {
+                            ui.radio_value(&mut self.spectrum_unit, SpectrumUnit::Dbfs, "dBFS");
+                            ui.radio_value(
+                                &mut self.spectrum_unit,
+                                SpectrumUnit::Psd,
+                                "功率谱密度 dBFS/Hz",
+                            );
+                        });
+                        ui.end_row();
+                        self.draw_display_range(ui);
+                        ui.label("对数坐标系数");
+                        ui.add(
+                            egui::Slider::new(&mut self.log_scale, 0.00000005..=1.0)
+                                .logarithmic(true),
+                        );
+                        ui.end_row();
+                        self.draw_window_options(ui);
+                        ui.label("频谱曲线");
+                        ui.checkbox(&mut self.show_traces, "显示");
+                        ui.end_row();
+                        self.draw_peak_options(ui);
                     });
                 ui.separator();
                 ui.label(format!("帧率：{:.2}", self.frame_counter.avg_frame_rate()));
                 ui.label(format!("未播放缓冲区：{:.2}", self.buffer_remain));
+                if let Some(a) = &self.audio_stream {
+                    ui.label(format!(
+                        "采样率：{} Hz  声道：{}",
+                        a.sample_rate(),
+                        a.channels()
+                    ));
+                    if let Some(status) = a.status() {
+                        ui.label(status);
+                    }
+                }
             });
+        if self.show_traces {
+            self.draw_traces(&ctx);
+        }
     }
-    //咱这个函数返回的元祖的第二个元素是当前的fft大小 第三个是因数
-    pub fn get_audio_stream_data(&mut self) -> Option<(Vec<f32>, u32, f32)> {
+    fn draw_peak_options(&mut self, ui: &mut egui::Ui) {
+        ui.label("峰值检测");
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.peaks_enabled, "开启");
+            ui.checkbox(&mut self.peak_markers, "在瀑布图上标出");
+        });
+        ui.end_row();
+        if !self.peaks_enabled {
+            self.peaks.clear();
+            return;
+        }
+        let settings = &mut self.peak_settings;
+        ui.label("阈值");
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::DragValue::new(&mut settings.threshold)
+                    .range(-240.0..=40.0)
+                    .suffix(" dB"),
+            );
+            ui.label("突出度");
+            ui.add(
+                egui::DragValue::new(&mut settings.prominence)
+                    .range(0.0..=100.0)
+                    .suffix(" dB"),
+            );
+            ui.label("个数");
+            ui.add(egui::DragValue::new(&mut settings.count).range(1..=50));
+        });
+        ui.end_row();
+        ui.label("插值");
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut settings.interpolation, Interpolation::Gaussian, "高斯");
+            ui.radio_value(
+                &mut settings.interpolation,
+                Interpolation::Parabolic,
+                "抛物线",
+            );
+        });
+        ui.end_row();
+        let Some(a) = &self.audio_stream else {
+            return;
+        };
+        for (lane, peaks) in self.peaks.iter().enumerate() {
+            if self.peaks.len() > 1 {
+                ui.label(format!("第 {} 路", lane + 1));
+            } else {
+                ui.label("");
+            }
+            ui.vertical(|ui| {
+                if peaks.is_empty() {
+                    ui.label("没有找到");
+                }
+                for peak in peaks {
+                    ui.label(format!(
+                        "{:.2} Hz  {:.1} dB",
+                        a.frequency_of(peak.bin),
+                        peak.level
+                    ));
+                }
+            });
+            ui.end_row();
+        }
+    }
+    //瀑布图看不出准确的电平 另外画一张当前频谱的曲线
+    fn draw_traces(&mut self, ctx: &Context) {
+        let mut open = self.show_traces;
+        egui::Window::new("频谱曲线")
+            .open(&mut open)
+            .resizable(true)
+            .default_size([640.0, 320.0])
+            .frame(
+                Frame::default()
+                    .fill(Color32::from_hex("#10101080").unwrap())
+                    .inner_margin(Margin::same(10.0))
+                    .rounding(Rounding::same(10.0)),
+            )
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.trace_current, "当前");
+                    ui.checkbox(&mut self.trace_average, "平均");
+                    ui.checkbox(&mut self.trace_max, "最大保持");
+                    ui.checkbox(&mut self.trace_min, "最小保持");
+                    if ui.button("重置").clicked() {
+                        self.traces.reset();
+                    }
+                });
+                let Some(a) = &self.audio_stream else {
+                    ui.label("还没有音源");
+                    return;
+                };
+                let unit = if a.output_is_db() { "dB" } else { "功率" };
+                let lanes = self.traces.lanes.len();
+                Plot::new("spectrum_traces")
+                    .legend(Legend::default())
+                    .x_axis_label("频率 Hz")
+                    .y_axis_label(unit)
+                    .show(ui, |plot_ui| {
+                        for (i, lane) in self.traces.lanes.iter().enumerate() {
+                            let suffix = if lanes > 1 {
+                                format!(" {}", i + 1)
+                            } else {
+                                String::new()
+                            };
+                            let average = if self.trace_average {
+                                lane.average()
+                            } else {
+                                Vec::new()
+                            };
+                            let traces = [
+                                (self.trace_current, "当前", &lane.current, false),
+                                (self.trace_average, "平均", &average, false),
+                                (self.trace_max, "最大保持", &lane.max, false),
+                                (self.trace_min, "最小保持", &lane.min, true),
+                            ];
+                            for (show, name, values, pick_min) in traces {
+                                if !show {
+                                    continue;
+                                }
+                                //点太多画不动 屏幕上也看不出来 合并到两千个点左右
+                                let points: Vec<[f64; 2]> = decimate(values, 2048, pick_min)
+                                    .into_iter()
+                                    .map(|(bin, v)| [a.frequency_of(bin) as f64, v as f64])
+                                    .collect();
+                                plot_ui.line(Line::new(points).name(format!("{name}{suffix}")));
+                            }
+                        }
+                    });
+            });
+        self.show_traces = open;
+    }
+    //按照界面上的选择创建音源
+    fn create_source(&self) -> Result<Box<dyn AudioSource>, anyhow::Error> {
+        match self.source_kind {
+            SourceKind::Device => Ok(Box::new(CpalSource::new(self.select_device.as_ref())?)),
+            SourceKind::File => {
+                //wav用hound直接读 其他格式交给symphonia解码
+                let path = Path::new(&self.file_path);
+                let is_wav = path
+                    .extension()
+                    .is_some_and(|e| e.eq_ignore_ascii_case("wav"));
+                let reader: Box<dyn FrameReader> = if is_wav {
+                    Box::new(WavFile::open(path)?)
+                } else {
+                    Box::new(DecodedFile::open(path)?)
+                };
+                Ok(Box::new(PlaybackSource::new(reader)))
+            }
+            SourceKind::Generator => {
+                let reader = Generator::new(self.generator.clone());
+                Ok(Box::new(PlaybackSource::new(Box::new(reader))))
+            }
+            SourceKind::Pipe => Ok(Box::new(PipeSource::new(
+                self.pipe_input.clone(),
+                self.pipe_format,
+                self.pipe_rate,
+                self.pipe_channels,
+            ))),
+            SourceKind::Network => Ok(Box::new(NetworkSource::new(
+                self.net_protocol,
+                self.net_port,
+            ))),
+        }
+    }
+    fn start_audio(&mut self) {
+        self.fail = None;
+        let pipe = (self.source_kind == SourceKind::Pipe).then(|| {
+            let input = self.pipe_input.clone();
+            (input, self.pipe_format, self.pipe_rate, self.pipe_channels)
+        });
+        //管道没法从头再读一遍 设置没变就接着用原来的音源 只是取消暂停
+        if let Some(audio) = &mut self.audio_stream {
+            if pipe.is_some() && pipe == self.pipe_running {
+                if let Err(e) = audio.start() {
+                    self.fail = Some(e.to_string());
+                }
+                return;
+            }
+        }
+        //先把旧的停掉 网络音源要先放开端口
+        drop(self.audio_stream.take());
+        self.pipe_running = pipe;
+        let mut audio = match self.create_source() {
+            Ok(source) => Audio::new(source),
+            Err(e) => {
+                self.fail = Some(e.to_string());
+                return;
+            }
+        };
+        if let Err(e) = audio.start() {
+            self.fail = Some(e.to_string());
+        }
+        self.audio_stream = Some(audio);
+    }
+    //音源选择和播放控制
+    fn draw_source_options(&mut self, ui: &mut egui::Ui) {
+        ui.label("音源");
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.source_kind, SourceKind::Device, "输入设备");
+            ui.selectable_value(&mut self.source_kind, SourceKind::File, "文件");
+            ui.selectable_value(&mut self.source_kind, SourceKind::Generator, "信号发生器");
+            ui.selectable_value(&mut self.source_kind, SourceKind::Pipe, "管道");
+            ui.selectable_value(&mut self.source_kind, SourceKind::Network, "网络");
+        });
+        ui.end_row();
+        match self.source_kind {
+            SourceKind::Device => {
+                ui.label("输入设备");
+                ui.horizontal(|ui| {
+                    let before = self.select_device.clone();
+                    egui::ComboBox::from_id_salt("input_device")
+                        .selected_text(
+                            self.select_device
+                                .as_ref()
+                                .map_or("默认设备".to_string(), |d| d.name.clone()),
+                        )
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.select_device, None, "默认设备");
+                            for device in &self.devices {
+                                let text = if device.is_default {
+                                    format!("{}: {} (默认)", device.id.host, device.id.name)
+                                } else {
+                                    format!("{}: {}", device.id.host, device.id.name)
+                                };
+                                ui.selectable_value(
+                                    &mut self.select_device,
+                                    Some(device.id.clone()),
+                                    text,
+                                );
+                            }
+                        });
+                    if ui.button("刷新").clicked() {
+                        self.devices = CpalSource::list_devices();
+                    }
+                    //选择变了就记下来 下次启动时自动选上
+                    if before != self.select_device {
+                        self.config.input_device = self.select_device.clone();
+                        if let Err(e) = self.config.save() {
+                            self.fail = Some(e.to_string());
+                        }
+                    }
+                });
+                ui.end_row();
+                if let Some(info) = self
+                    .devices
+                    .iter()
+                    .find(|d| Some(&d.id) == self.select_device.as_ref())
+                {
+                    ui.label("设备参数");
+                    let rates: Vec<String> = info
+                        .sample_rates
+                        .iter()
+                        .map(|(min, max)| {
+                            if min == max {
+                                format!("{min}")
+                            } else {
+                                format!("{min}-{max}")
+                            }
+                        })
+                        .collect();
+                    ui.label(format!(
+                        "声道 {:?} 采样率 {} Hz 格式 {:?}",
+                        info.channels,
+                        rates.join("/"),
+                        info.formats
+                    ));
+                    ui.end_row();
+                }
+            }
+            SourceKind::File => {
+                ui.label("文件");
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.file_path)
+                        .on_hover_text("wav、flac、mp3、ogg vorbis、aac(m4a)");
+                    if ui.button("打开").clicked() {
+                        self.start_audio();
+                    }
+                });
+                ui.end_row();
+            }
+            SourceKind::Generator => self.draw_generator_options(ui),
+            SourceKind::Pipe => self.draw_pipe_options(ui),
+            SourceKind::Network => {
+                ui.label("监听");
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut self.net_protocol, Protocol::Udp, "UDP");
+                    ui.selectable_value(&mut self.net_protocol, Protocol::Tcp, "TCP");
+                    ui.add(egui::DragValue::new(&mut self.net_port).prefix("端口 "));
+                });
+                ui.end_row();
+            }
+        }
+        //能暂停和跳转的音源显示播放控制 信号发生器没有长度 只能暂停
+        if let Some(transport) = self.audio_stream.as_ref().and_then(|a| a.transport()) {
+            ui.label("播放");
+            ui.horizontal(|ui| {
+                let playing = transport.is_playing();
+                if ui.button(if playing { "⏸" } else { "▶" }).clicked() {
+                    transport.set_playing(!playing);
+                }
+                let mut looping = transport.is_looping();
+                if transport.duration().is_some() && ui.checkbox(&mut looping, "循环").changed() {
+                    transport.set_looping(looping);
+                }
+            });
+            ui.end_row();
+            if let Some(duration) = transport.duration() {
+                ui.label("进度");
+                let mut position = transport.position();
+                let slider = egui::Slider::new(&mut position, 0.0..=duration)
+                    .custom_formatter(|v, _| format!("{:02}:{:04.1}", (v / 60.0) as u32, v % 60.0));
+                if ui.add(slider).changed() {
+                    transport.seek(position);
+                }
+                ui.end_row();
+            }
+            if let Some(e) = transport.error() {
+                self.fail = Some(e);
+            }
+        }
+    }
+    fn draw_generator_options(&mut self, ui: &mut egui::Ui) {
+        let mut g = self.generator.lock().unwrap();
+        ui.label("波形");
+        egui::ComboBox::from_id_salt("waveform")
+            .selected_text(format!("{:?}", g.waveform))
+            .show_ui(ui, |ui| {
+                for waveform in [
+                    Waveform::Sine,
+                    Waveform::MultiTone,
+                    Waveform::LinearChirp,
+                    Waveform::LogChirp,
+                    Waveform::Square,
+                    Waveform::Impulse,
+                    Waveform::WhiteNoise,
+                    Waveform::PinkNoise,
+                ] {
+                    ui.selectable_value(&mut g.waveform, waveform, format!("{waveform:?}"));
+                }
+            });
+        ui.end_row();
+        ui.label("电平");
+        ui.add(egui::Slider::new(&mut g.level, -120.0..=0.0).suffix(" dBFS"));
+        ui.end_row();
+        let nyquist = g.sample_rate as f32 / 2.0;
+        match g.waveform {
+            Waveform::MultiTone => {
+                ui.label("频率列表");
+                if ui.text_edit_singleline(&mut self.tones_text).changed() {
+                    g.tones = self
+                        .tones_text
+                        .split(',')
+                        .filter_map(|t| t.trim().parse().ok())
+                        .collect();
+                }
+                ui.end_row();
+            }
+            Waveform::LinearChirp | Waveform::LogChirp => {
+                ui.label("起始频率");
+                ui.add(
+                    egui::Slider::new(&mut g.frequency, 1.0..=nyquist)
+                        .logarithmic(true)
+                        .suffix(" Hz"),
+                );
+                ui.end_row();
+                ui.label("终止频率");
+                ui.add(
+                    egui::Slider::new(&mut g.end_frequency, 1.0..=nyquist)
+                        .logarithmic(true)
+                        .suffix(" Hz"),
+                );
+                ui.end_row();
+                ui.label("扫频时间");
+                ui.add(
+                    egui::Slider::new(&mut g.sweep_time, 0.1..=60.0)
+                        .logarithmic(true)
+                        .suffix(" s"),
+                );
+                ui.end_row();
+            }
+            Waveform::Sine | Waveform::Square | Waveform::Impulse => {
+                ui.label("频率");
+                ui.add(
+                    egui::Slider::new(&mut g.frequency, 1.0..=nyquist)
+                        .logarithmic(true)
+                        .suffix(" Hz"),
+                );
+                ui.end_row();
+            }
+            Waveform::WhiteNoise | Waveform::PinkNoise => {}
+        }
+        ui.label("采样率");
+        egui::ComboBox::from_id_salt("generator_rate")
+            .selected_text(format!("{} Hz", g.sample_rate))
+            .show_ui(ui, |ui| {
+                for rate in [8000, 16000, 22050, 44100, 48000, 96000, 192000] {
+                    ui.selectable_value(&mut g.sample_rate, rate, format!("{rate} Hz"));
+                }
+            })
+            .response
+            .on_hover_text("采样率在重新开始后生效");
+        ui.end_row();
+    }
+    fn draw_pipe_options(&mut self, ui: &mut egui::Ui) {
+        ui.label("输入");
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.pipe_input, PipeInput::Stdin, "标准输入");
+            let fifo = PipeInput::Fifo(self.pipe_path.clone().into());
+            ui.selectable_value(&mut self.pipe_input, fifo, "命名管道");
+            if let PipeInput::Fifo(path) = &mut self.pipe_input {
+                if ui.text_edit_singleline(&mut self.pipe_path).changed() {
+                    *path = self.pipe_path.clone().into();
+                }
+            }
+        });
+        ui.end_row();
+        ui.label("格式");
+        egui::ComboBox::from_id_salt("pipe_format")
+            .selected_text(self.pipe_format.name())
+            .show_ui(ui, |ui| {
+                for format in RawFormat::ALL {
+                    ui.selectable_value(&mut self.pipe_format, format, format.name());
+                }
+            });
+        ui.end_row();
+        ui.label("采样率");
+        ui.add(
+            egui::DragValue::new(&mut self.pipe_rate)
+                .range(1000..=768000)
+                .suffix(" Hz"),
+        );
+        ui.end_row();
+        ui.label("声道数");
+        ui.add(egui::DragValue::new(&mut self.pipe_channels).range(1..=64));
+        ui.end_row();
+    }
+    fn draw_display_range(&mut self, ui: &mut egui::Ui) {
+        let range = &mut self.display_range;
+        ui.label("显示范围");
+        let r = ui.horizontal(|ui| {
+            let floor = ui.add(
+                egui::DragValue::new(&mut range.floor)
+                    .range(-240.0..=range.ceiling - 1.0)
+                    .suffix(" dB"),
+            );
+            ui.label("到");
+            let ceiling = ui.add(
+                egui::DragValue::new(&mut range.ceiling)
+                    .range(range.floor + 1.0..=40.0)
+                    .suffix(" dB"),
+            );
+            floor | ceiling
+        });
+        ui.end_row();
+        ui.label("伽马");
+        let gamma = ui.add(egui::Slider::new(&mut range.gamma, 0.2..=5.0).logarithmic(true));
+        ui.end_row();
+        //改完了才保存 免得拖动的时候一直写文件
+        if committed(&(r.inner | gamma)) {
+            self.config.display_range = self.display_range;
+            if let Err(e) = self.config.save() {
+                self.fail = Some(e.to_string());
+            }
+        }
+    }
+    fn draw_window_options(&mut self, ui: &mut egui::Ui) {
+        ui.label("FFT 窗函数");
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt("fft_window")
+                .selected_text(self.select_fftwindow.name())
+                .show_ui(ui, |ui| {
+                    for window in FFTWindow::ALL {
+                        //同一种窗再点一下不要把调好的参数重置掉
+                        let selected = self.select_fftwindow.same_kind(&window);
+                        if ui.selectable_label(selected, window.name()).clicked() && !selected {
+                            self.select_fftwindow = window;
+                        }
+                    }
+                });
+            if let Some((name, value, range)) = self.select_fftwindow.parameter_mut() {
+                ui.label(name);
+                ui.add(egui::DragValue::new(value).range(range).speed(0.01));
+            }
+        });
+        ui.end_row();
+    }
+    fn draw_analysis_options(&mut self, ui: &mut egui::Ui) {
+        ui.label("分析方式");
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.analysis_mode, AnalysisMode::Fft, "FFT");
+            ui.radio_value(&mut self.analysis_mode, AnalysisMode::Cqt, "常数Q变换");
+            ui.radio_value(&mut self.analysis_mode, AnalysisMode::Mel, "梅尔");
+            ui.radio_value(&mut self.analysis_mode, AnalysisMode::Reassigned, "重排");
+            ui.radio_value(&mut self.analysis_mode, AnalysisMode::Multitaper, "多窗");
+        });
+        ui.end_row();
+        match self.analysis_mode {
+            AnalysisMode::Fft => {
+                self.draw_fft_size(ui);
+                self.draw_window_len(ui);
+                self.draw_zoom_options(ui);
+            }
+            AnalysisMode::Cqt => self.draw_cqt_options(ui),
+            AnalysisMode::Mel => {
+                self.draw_fft_size(ui);
+                self.draw_window_len(ui);
+                self.draw_mel_options(ui);
+            }
+            AnalysisMode::Reassigned => {
+                self.draw_fft_size(ui);
+                self.draw_window_len(ui);
+                ui.label("重排方式");
+                ui.horizontal(|ui| {
+                    ui.radio_value(
+                        &mut self.reassign_mode,
+                        ReassignMode::Synchrosqueeze,
+                        "同步压缩",
+                    )
+                    .on_hover_text("只在频率方向上挪");
+                    ui.radio_value(&mut self.reassign_mode, ReassignMode::Reassign, "时频重排")
+                        .on_hover_text("时间方向也挪 画面会晚半个窗长");
+                });
+                ui.end_row();
+            }
+            AnalysisMode::Multitaper => {
+                self.draw_fft_size(ui);
+                self.draw_window_len(ui);
+                self.draw_multitaper_options(ui);
+            }
+        }
+        //离开梅尔模式就停止导出
+        if self.analysis_mode != AnalysisMode::Mel {
+            self.mel_export = None;
+        }
+    }
+    fn draw_mel_options(&mut self, ui: &mut egui::Ui) {
+        let mel = &mut self.mel;
+        ui.label("梅尔带数");
+        ui.add(egui::DragValue::new(&mut mel.bands).range(1..=512));
+        ui.end_row();
+        ui.label("频率范围");
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::DragValue::new(&mut mel.min_freq)
+                    .range(0.0..=mel.max_freq)
+                    .suffix(" Hz"),
+            );
+            ui.label("到");
+            ui.add(
+                egui::DragValue::new(&mut mel.max_freq)
+                    .range(mel.min_freq..=96000.0)
+                    .suffix(" Hz"),
+            );
+        });
+        ui.end_row();
+        ui.label("梅尔公式");
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut mel.scale, MelScale::Slaney, "Slaney");
+            ui.radio_value(&mut mel.scale, MelScale::Htk, "HTK");
+            ui.checkbox(&mut mel.log, "取对数 (dB)");
+        });
+        ui.end_row();
+        ui.label("导出 CSV");
+        ui.horizontal(|ui| {
+            ui.add_enabled(
+                self.mel_export.is_none(),
+                egui::TextEdit::singleline(&mut self.mel_export_path).desired_width(160.0),
+            );
+            match &self.mel_export {
+                Some(export) => {
+                    let rows = export.rows;
+                    if ui.button("停止").clicked() {
+                        self.mel_export = None;
+                    }
+                    ui.label(format!("已写 {rows} 行"));
+                }
+                None => {
+                    if ui.button("开始").clicked() {
+                        self.start_mel_export();
+                    }
+                }
+            }
+        });
+        ui.end_row();
+    }
+    fn start_mel_export(&mut self) {
+        let Some(centers) = self.audio_stream.as_ref().and_then(|a| a.mel_centers()) else {
+            self.fail = Some("先开始分析再导出".to_string());
+            return;
+        };
+        match MelExport::create(Path::new(&self.mel_export_path), centers) {
+            Ok(export) => {
+                self.mel_export = Some(export);
+                self.mel_export_time = 0.0;
+            }
+            Err(e) => self.fail = Some(e.to_string()),
+        }
+    }
+    fn draw_cqt_options(&mut self, ui: &mut egui::Ui) {
+        let cqt = &mut self.cqt;
+        ui.label("每倍频程");
+        let bins = ui.add(
+            egui::DragValue::new(&mut cqt.bins_per_octave)
+                .range(1..=96)
+                .suffix(" 个频点"),
+        );
+        ui.end_row();
+        ui.label("频率范围");
+        let range = ui.horizontal(|ui| {
+            let min = ui.add(
+                egui::DragValue::new(&mut cqt.min_freq)
+                    .range(20.0..=cqt.max_freq)
+                    .suffix(" Hz"),
+            );
+            ui.label("到");
+            let max = ui.add(
+                egui::DragValue::new(&mut cqt.max_freq)
+                    .range(cqt.min_freq..=96000.0)
+                    .suffix(" Hz"),
+            );
+            min | max
+        });
+        ui.end_row();
+        //重新算核比较慢 改完了才生效
+        if committed(&(bins | range.inner)) {
+            self.cqt_applied = *cqt;
+        }
+        //最低频率决定了一帧有多长 也就是时间分辨率
+        if let Some(a) = &self.audio_stream {
+            let q = 1.0 / (2f32.powf(1.0 / cqt.bins_per_octave as f32) - 1.0);
+            let frame = q / cqt.min_freq;
+            ui.label("帧长");
+            ui.label(format!(
+                "{:.0} 毫秒  {:.0} 个采样",
+                frame * 1000.0,
+                frame * a.sample_rate() as f32
+            ));
+            ui.end_row();
+            if a.cqt_pending() {
+                ui.label("");
+                ui.label("正在计算核...");
+                ui.end_row();
+            }
+        }
+    }
+    fn draw_multitaper_options(&mut self, ui: &mut egui::Ui) {
+        let mt = &mut self.multitaper;
+        ui.label("时间带宽积 NW");
+        let nw = ui.add(
+            egui::DragValue::new(&mut mt.nw)
+                .range(1.0..=20.0)
+                .speed(0.1),
+        );
+        ui.end_row();
+        //超过2NW-1个的窗泄漏很大 不让选
+        let max_tapers = ((2.0 * mt.nw).floor() as usize).saturating_sub(1).max(1);
+        mt.tapers = mt.tapers.clamp(1, max_tapers);
+        ui.label("窗的个数 K");
+        let tapers = ui.add(egui::DragValue::new(&mut mt.tapers).range(1..=max_tapers));
+        ui.end_row();
+        //算Slepian窗要解特征向量 改完了才生效
+        if committed(&(nw | tapers)) {
+            self.multitaper_applied = *mt;
+        }
+        //主瓣宽度是2W 比单个窗宽 换来的是方差降到1/K
+        if let Some(a) = &self.audio_stream {
+            let bandwidth = 2.0 * mt.nw * a.sample_rate() as f32 / self.window_len as f32;
+            ui.label("分辨率");
+            ui.label(format!("{bandwidth:.2} Hz"));
+            ui.end_row();
+        }
+    }
+    //缩放模式下出来的是复数 有fftsize个频点 不是一半
+    fn max_fft_size(&self) -> u32 {
+        if self.analysis_mode == AnalysisMode::Fft && self.zoom_enabled {
+            MAX_BUFFER_SIZE as u32
+        } else {
+            MAX_FFT_SIZE
+        }
+    }
+    fn draw_fft_size(&mut self, ui: &mut egui::Ui) {
+        ui.label("FFT 大小");
+        ui.horizontal(|ui| {
+            let max = self.max_fft_size();
+            ui.add(egui::Slider::new(&mut self.fftsize, 32..=max).logarithmic(true));
+            if let Some(a) = &self.audio_stream {
+                let bin_width = a.bin_width();
+                ui.label(format!("{bin_width:.3} Hz/频点"));
+                //fft大小等于采样率的时候 一个频点正好1Hz 缩放模式下是抽取以后的采样率
+                if ui.button("1 Hz").clicked() {
+                    let rate = bin_width * self.fftsize as f32;
+                    self.fftsize = (rate.round() as u32).clamp(32, max);
+                }
+            }
+        });
+        ui.end_row();
+    }
+    fn draw_zoom_options(&mut self, ui: &mut egui::Ui) {
+        ui.label("缩放 FFT");
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.zoom_enabled, "");
+            let nyquist = self
+                .audio_stream
+                .as_ref()
+                .map_or(24000.0, |a| a.sample_rate() as f32 / 2.0);
+            ui.add_enabled(
+                self.zoom_enabled,
+                egui::DragValue::new(&mut self.zoom.center)
+                    .range(0.0..=nyquist)
+                    .prefix("中心 ")
+                    .suffix(" Hz"),
+            );
+            ui.add_enabled(
+                self.zoom_enabled,
+                egui::DragValue::new(&mut self.zoom.decimation)
+                    .range(2..=4096)
+                    .prefix("抽取 ")
+                    .suffix(" 倍"),
+            );
+            if let Some(a) = &self.audio_stream {
+                let span = a.sample_rate() as f32 / self.zoom.decimation as f32;
+                ui.label(format!("宽 {span:.2} Hz"));
+            }
+        });
+        ui.end_row();
+    }
+    fn draw_window_len(&mut self, ui: &mut egui::Ui) {
+        ui.label("窗长");
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.zero_pad, "补零");
+            if self.zero_pad {
+                ui.add(
+                    egui::Slider::new(&mut self.window_len, 16..=self.fftsize).logarithmic(true),
+                );
+                ui.label(format!(
+                    "插值 {:.1} 倍",
+                    self.fftsize as f32 / self.window_len as f32
+                ));
+            } else {
+                ui.label("和 FFT 大小一样");
+            }
+        });
+        ui.end_row();
+    }
+    fn draw_overlap_options(&mut self, ui: &mut egui::Ui) {
+        ui.label("帧重叠");
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt("overlap")
+                .selected_text(format!("{}%", self.overlap * 100.0))
+                .show_ui(ui, |ui| {
+                    for overlap in [0.0, 0.5, 0.75, 0.875, 0.9375] {
+                        ui.selectable_value(
+                            &mut self.overlap,
+                            overlap,
+                            format!("{}%", overlap * 100.0),
+                        );
+                    }
+                });
+            //换算成帧移和每秒出多少列 方便看时间分辨率
+            let hop = self.hop_size();
+            match &self.audio_stream {
+                Some(a) => ui.label(format!(
+                    "帧移 {hop}  {:.1} 列/秒",
+                    a.sample_rate() as f32 / hop as f32
+                )),
+                None => ui.label(format!("帧移 {hop}")),
+            };
+        });
+        ui.end_row();
+    }
+    fn draw_average_options(&mut self, ui: &mut egui::Ui) {
+        ui.label("谱平均");
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.average_mode, AverageMode::Off, "关");
+            ui.radio_value(&mut self.average_mode, AverageMode::Linear, "线性");
+            ui.radio_value(&mut self.average_mode, AverageMode::Exponential, "指数");
+            ui.radio_value(&mut self.average_mode, AverageMode::PeakHold, "峰值保持");
+            match self.average_mode {
+                AverageMode::Off => {}
+                AverageMode::Linear => {
+                    ui.add(
+                        egui::DragValue::new(&mut self.average_frames)
+                            .range(1..=1024)
+                            .suffix(" 帧"),
+                    );
+                }
+                AverageMode::Exponential => {
+                    ui.add(
+                        egui::DragValue::new(&mut self.average_time)
+                            .range(0.01..=60.0)
+                            .speed(0.01)
+                            .suffix(" 秒"),
+                    );
+                }
+                AverageMode::PeakHold => {
+                    ui.add(
+                        egui::DragValue::new(&mut self.peak_decay)
+                            .range(0.0..=200.0)
+                            .suffix(" dB/秒"),
+                    );
+                }
+            }
+            if self.average_mode != AverageMode::Off && ui.button("重置").clicked() {
+                self.averager.reset();
+            }
+        });
+        ui.end_row();
+        self.averager.set_mode(match self.average_mode {
+            AverageMode::Off => Averaging::Off,
+            AverageMode::Linear => Averaging::Linear(self.average_frames),
+            AverageMode::Exponential => Averaging::Exponential(self.average_time),
+            AverageMode::PeakHold => Averaging::PeakHold(self.peak_decay),
+        });
+    }
+    fn hop_size(&self) -> usize {
+        ((self.window_len as f32 * (1.0 - self.overlap)).round() as usize).max(1)
+    }
+    //咱这个函数返回的元祖的第一个元素是每一路的频谱 第二个是当前的fft大小 第三个是颜色的dB范围
+    pub fn get_audio_stream_data(&mut self) -> Option<(Vec<Vec<f32>>, u32, DisplayRange)> {
         let a = self.audio_stream.as_mut()?.fetch_data();
         self.buffer_remain = a.as_ref()?.1;
-        Some((a.unwrap().0, self.fftsize, self.value_gain_factor))
+        let spectrums = a.unwrap().0;
+        if let Some(export) = &mut self.mel_export {
+            let written = spectrums
+                .iter()
+                .enumerate()
+                .try_for_each(|(lane, s)| export.write(self.mel_export_time, lane, s));
+            if let Err(e) = written {
+                self.fail = Some(e.to_string());
+                self.mel_export = None;
+            }
+            let rate = self.audio_stream.as_ref()?.sample_rate();
+            self.mel_export_time += self.hop_size() as f64 / rate as f64;
+        }
+        //导出的是原始的 平均只影响显示
+        let a = self.audio_stream.as_ref()?;
+        let mut spectrums = self
+            .averager
+            .process(spectrums, a.frame_period(), a.output_is_db());
+        if self.show_traces {
+            self.traces.push(&spectrums, a.output_is_db());
+        }
+        //着色器按dB上色 线性的只在显示的时候换成dB 峰也在显示的这份上找
+        if !a.output_is_db() {
+            for spectrum in &mut spectrums {
+                spectrum.iter_mut().for_each(|x| *x = audio::power_to_db(*x));
+            }
+        }
+        if self.peaks_enabled {
+            self.peaks = spectrums
+                .iter()
+                .map(|s| find_peaks(s, &self.peak_settings))
+                .collect();
+            if self.peak_markers {
+                for (spectrum, peaks) in spectrums.iter_mut().zip(&self.peaks) {
+                    for peak in peaks {
+                        spectrum[peak.bin.round() as usize] = PEAK_MARKER;
+                    }
+                }
+            }
+        } else {
+            self.peaks.clear();
+        }
+        Some((spectrums, self.fftsize, self.display_range))
     }
     fn end_frame_and_draw<'a, 'b>(
         &'a mut self,
@@ -237,14 +1328,31 @@ impl EguiApp {
     }
     //更新ui中的参数到audio中
     fn update_argument(&mut self) {
-        //确保fftsize是2的整数幂
-        let t: u32 = (self.fftsize as f32).log2().round() as u32;
-        self.fftsize = 2u32.pow(t);
+        //任意长度都可以 不用是2的整数幂
+        self.fftsize = self.fftsize.clamp(32, self.max_fft_size());
+        if !self.zero_pad {
+            self.window_len = self.fftsize;
+        }
+        self.window_len = self.window_len.min(self.fftsize);
+        let hop = self.hop_size();
         if let Some(a) = &mut self.audio_stream {
             //更新窗函数
             a.set_fft_window_func(self.select_fftwindow);
             //更新fftsize
-            a.set_fft_size(self.fftsize as usize);
+            a.set_fft_size(self.fftsize as usize, self.window_len as usize);
+            a.set_hop_size(hop);
+            a.set_spectrum_unit(self.spectrum_unit);
+            //缩放只在普通fft下有意义
+            let fft = self.analysis_mode == AnalysisMode::Fft;
+            a.set_zoom((fft && self.zoom_enabled).then_some(self.zoom));
+            a.set_analysis(match self.analysis_mode {
+                AnalysisMode::Fft => Analysis::Fft,
+                AnalysisMode::Cqt => Analysis::Cqt(self.cqt_applied),
+                AnalysisMode::Mel => Analysis::Mel(self.mel),
+                AnalysisMode::Reassigned => Analysis::Reassigned(self.reassign_mode),
+                AnalysisMode::Multitaper => Analysis::Multitaper(self.multitaper_applied),
+            });
+            a.set_channel_mode(self.select_channel_mode);
         }
     }
     pub fn update<'a>(
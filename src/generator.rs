@@ -0,0 +1,152 @@
+use std::{
+    f64::consts::TAU,
+    sync::{Arc, Mutex},
+};
+
+use rand::Rng;
+
+use crate::playback::FrameReader;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Waveform {
+    Sine,
+    MultiTone,
+    LinearChirp,
+    LogChirp,
+    Square,
+    Impulse,
+    WhiteNoise,
+    PinkNoise,
+}
+//信号发生器的参数 界面和生成线程共享 除了采样率以外改了马上生效
+#[derive(Debug, Clone)]
+pub struct GeneratorSettings {
+    pub waveform: Waveform,
+    pub sample_rate: u32,
+    pub level: f32,         //峰值电平 dBFS 满幅正弦是0dB
+    pub frequency: f32,     //正弦、方波的频率 冲激串的重复频率 扫频的起始频率
+    pub end_frequency: f32, //扫频的终止频率
+    pub sweep_time: f32,    //扫一遍的秒数
+    pub tones: Vec<f32>,    //多音的各个频率
+}
+impl Default for GeneratorSettings {
+    fn default() -> Self {
+        Self {
+            waveform: Waveform::Sine,
+            sample_rate: 48000,
+            level: -20.0,
+            frequency: 1000.0,
+            end_frequency: 20000.0,
+            sweep_time: 5.0,
+            tones: vec![440.0, 1000.0, 5000.0],
+        }
+    }
+}
+
+//合成的单声道信号 无限长
+pub struct Generator {
+    settings: Arc<Mutex<GeneratorSettings>>,
+    sample_rate: u32,
+    phases: Vec<f64>, //每个音各自的相位 单位是周
+    sweep: f64,       //扫频进行到的秒数
+    pink: [f32; 7],   //粉红噪声滤波器的状态
+}
+impl Generator {
+    pub fn new(settings: Arc<Mutex<GeneratorSettings>>) -> Self {
+        let sample_rate = settings.lock().unwrap().sample_rate;
+        Self {
+            settings,
+            sample_rate,
+            phases: Vec::new(),
+            sweep: 0.0,
+            pink: [0.0; 7],
+        }
+    }
+    //Paul Kellet的粉红噪声滤波器 输入白噪声
+    fn pink(&mut self, white: f32) -> f32 {
+        let b = &mut self.pink;
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.153852;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+        let out = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+        b[6] = white * 0.115926;
+        //滤波器的增益大约是3.5 除掉让峰值差不多回到1
+        out / 3.5
+    }
+}
+impl FrameReader for Generator {
+    fn read(&mut self, frames: usize) -> Result<Vec<f32>, anyhow::Error> {
+        let settings = self.settings.lock().unwrap().clone();
+        let fs = self.sample_rate as f64;
+        let amplitude = 10f32.powf(settings.level / 20.0);
+        let tones: &[f32] = match settings.waveform {
+            Waveform::MultiTone => &settings.tones,
+            _ => std::slice::from_ref(&settings.frequency),
+        };
+        self.phases.resize(tones.len(), 0.0);
+        let mut rng = rand::rng();
+        let mut data = Vec::with_capacity(frames);
+        for _ in 0..frames {
+            let sample = match settings.waveform {
+                Waveform::Sine | Waveform::MultiTone => {
+                    let mut sum = 0.0;
+                    for (phase, f) in self.phases.iter_mut().zip(tones) {
+                        sum += (*phase * TAU).sin() as f32;
+                        *phase = (*phase + *f as f64 / fs).fract();
+                    }
+                    sum
+                }
+                Waveform::LinearChirp | Waveform::LogChirp => {
+                    let t = self.sweep / settings.sweep_time.max(0.001) as f64;
+                    let (f0, f1) = (settings.frequency as f64, settings.end_frequency as f64);
+                    let f = if settings.waveform == Waveform::LinearChirp {
+                        f0 + (f1 - f0) * t
+                    } else {
+                        f0.max(1.0) * (f1 / f0.max(1.0)).powf(t)
+                    };
+                    let s = (self.phases[0] * TAU).sin() as f32;
+                    self.phases[0] = (self.phases[0] + f / fs).fract();
+                    self.sweep += 1.0 / fs;
+                    if self.sweep >= settings.sweep_time as f64 {
+                        self.sweep = 0.0;
+                    }
+                    s
+                }
+                Waveform::Square => {
+                    let s = if self.phases[0] < 0.5 { 1.0 } else { -1.0 };
+                    self.phases[0] = (self.phases[0] + settings.frequency as f64 / fs).fract();
+                    s
+                }
+                Waveform::Impulse => {
+                    //相位刚绕回一圈的那个样本放一个冲激
+                    let step = settings.frequency as f64 / fs;
+                    let s = if self.phases[0] < step { 1.0 } else { 0.0 };
+                    self.phases[0] = (self.phases[0] + step).fract();
+                    s
+                }
+                Waveform::WhiteNoise => rng.random_range(-1.0..1.0),
+                Waveform::PinkNoise => {
+                    let white = rng.random_range(-1.0..1.0);
+                    self.pink(white)
+                }
+            };
+            data.push(sample * amplitude);
+        }
+        Ok(data)
+    }
+    fn seek(&mut self, _frame: u64) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+    fn channels(&self) -> usize {
+        1
+    }
+    fn length(&self) -> Option<u64> {
+        None
+    }
+}
@@ -4,8 +4,33 @@ mod egui_app;
 mod winit_app;
 mod audio;
 mod compute;
+mod config;
+mod args;
+mod playback;
+mod wav;
+mod decoder;
+mod generator;
+mod pcm;
+mod pipe;
+mod network;
+mod window;
+mod zoom;
+mod cqt;
+mod mel;
+mod reassign;
+mod multitaper;
+mod average;
+mod traces;
+mod peaks;
 fn main(){
     env_logger::init();
-    let mut app=winit_app::App::new();
+    let args = match args::Args::parse() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(2);
+        }
+    };
+    let mut app=winit_app::App::new(args);
     app.run();
 }
\ No newline at end of file
@@ -0,0 +1,238 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+//赫兹和梅尔怎么换算
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MelScale {
+    Htk,    //2595·log10(1+f/700) 三角形峰值是1
+    Slaney, //1kHz以下线性 以上对数 三角形按面积归一化 和librosa默认的一样
+}
+impl MelScale {
+    fn to_mel(self, f: f64) -> f64 {
+        match self {
+            MelScale::Htk => 2595.0 * (1.0 + f / 700.0).log10(),
+            MelScale::Slaney => {
+                let step = 200.0 / 3.0;
+                let log_step = 6.4f64.ln() / 27.0;
+                if f < 1000.0 {
+                    f / step
+                } else {
+                    1000.0 / step + (f / 1000.0).ln() / log_step
+                }
+            }
+        }
+    }
+    fn to_hz(self, m: f64) -> f64 {
+        match self {
+            MelScale::Htk => 700.0 * (10f64.powf(m / 2595.0) - 1.0),
+            MelScale::Slaney => {
+                let step = 200.0 / 3.0;
+                let log_step = 6.4f64.ln() / 27.0;
+                let knee = 1000.0 / step;
+                if m < knee {
+                    m * step
+                } else {
+                    1000.0 * ((m - knee) * log_step).exp()
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MelSettings {
+    pub bands: usize,
+    pub min_freq: f32,
+    pub max_freq: f32,
+    pub scale: MelScale,
+    pub log: bool, //输出dB 不勾就是线性的能量
+}
+impl Default for MelSettings {
+    fn default() -> Self {
+        Self {
+            bands: 128,
+            min_freq: 0.0,
+            max_freq: 8000.0,
+            scale: MelScale::Slaney,
+            log: true,
+        }
+    }
+}
+
+//一个三角形滤波器 只存不为0的那一段
+struct Filter {
+    start: usize,
+    weights: Vec<f32>,
+}
+
+//梅尔滤波器组 作用在fft的功率谱上 fft大小或者采样率变了要重新建
+pub struct MelFilterbank {
+    pub settings: MelSettings,
+    pub sample_rate: u32,
+    pub fftsize: usize,
+    centers: Vec<f32>, //每个带的中心频率
+    filters: Vec<Filter>,
+}
+impl MelFilterbank {
+    pub fn new(settings: MelSettings, sample_rate: u32, fftsize: usize) -> Self {
+        let scale = settings.scale;
+        let bands = settings.bands.max(1);
+        let nyquist = sample_rate as f64 / 2.0;
+        let min_mel = scale.to_mel((settings.min_freq as f64).clamp(0.0, nyquist));
+        let max_mel = scale.to_mel((settings.max_freq as f64).clamp(0.0, nyquist));
+        //bands个三角形 要bands+2个边界点 在梅尔刻度上等间距
+        let edges: Vec<f64> = (0..bands + 2)
+            .map(|i| scale.to_hz(min_mel + (max_mel - min_mel) * i as f64 / (bands + 1) as f64))
+            .collect();
+        let bin_hz = sample_rate as f64 / fftsize as f64;
+        let bins = fftsize / 2 + 1;
+        let filters = edges
+            .windows(3)
+            .map(|e| {
+                let (left, center, right) = (e[0], e[1], e[2]);
+                //Slaney按面积归一化 带越宽高度越低
+                let height = match scale {
+                    MelScale::Htk => 1.0,
+                    MelScale::Slaney => 2.0 / (right - left).max(f64::EPSILON),
+                };
+                let start = ((left / bin_hz).ceil() as usize).min(bins);
+                let end = ((right / bin_hz).floor() as usize + 1).clamp(start, bins);
+                let weights = (start..end)
+                    .map(|k| {
+                        let f = k as f64 * bin_hz;
+                        let w = if f <= center {
+                            (f - left) / (center - left).max(f64::EPSILON)
+                        } else {
+                            (right - f) / (right - center).max(f64::EPSILON)
+                        };
+                        (w.max(0.0) * height) as f32
+                    })
+                    .collect();
+                Filter { start, weights }
+            })
+            .collect();
+        Self {
+            settings,
+            sample_rate,
+            fftsize,
+            centers: edges[1..=bands].iter().map(|f| *f as f32).collect(),
+            filters,
+        }
+    }
+    pub fn centers(&self) -> &[f32] {
+        &self.centers
+    }
+    //第band个带的中心频率 带小数的在两个带之间插值
+    pub fn frequency_of(&self, band: f32) -> f32 {
+        let last = self.centers.len() - 1;
+        let i = (band.max(0.0) as usize).min(last);
+        let next = (i + 1).min(last);
+        let t = band - i as f32;
+        self.centers[i] + (self.centers[next] - self.centers[i]) * t
+    }
+    //输入是线性的功率谱 输出每个带的能量
+    pub fn apply(&self, power: &[f32]) -> Vec<f32> {
+        self.filters
+            .iter()
+            .map(|filter| {
+                power
+                    .get(filter.start..filter.start + filter.weights.len())
+                    .map_or(0.0, |p| {
+                        p.iter().zip(&filter.weights).map(|(p, w)| p * w).sum()
+                    })
+            })
+            .collect()
+    }
+}
+
+//把梅尔特征一行一行写进csv 第一行是各个带的中心频率
+pub struct MelExport {
+    writer: BufWriter<File>,
+    pub rows: usize,
+}
+impl MelExport {
+    pub fn create(path: &Path, centers: &[f32]) -> Result<Self, anyhow::Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        write!(writer, "time,lane")?;
+        for f in centers {
+            write!(writer, ",{f:.2}")?;
+        }
+        writeln!(writer)?;
+        Ok(Self { writer, rows: 0 })
+    }
+    pub fn write(&mut self, time: f64, lane: usize, values: &[f32]) -> Result<(), anyhow::Error> {
+        write!(self.writer, "{time:.6},{lane}")?;
+        for v in values {
+            write!(self.writer, ",{v}")?;
+        }
+        writeln!(self.writer)?;
+        self.rows += 1;
+        Ok(())
+    }
+}
+impl Drop for MelExport {
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scale_conversions() {
+        //HTK的700Hz正好是2595·log10(2)
+        assert!((MelScale::Htk.to_mel(700.0) - 2595.0 * 2f64.log10()).abs() < 1e-9);
+        //Slaney的1kHz是15 往上每27个梅尔是6.4倍
+        assert!((MelScale::Slaney.to_mel(1000.0) - 15.0).abs() < 1e-9);
+        assert!((MelScale::Slaney.to_mel(6400.0) - 42.0).abs() < 1e-9);
+        for scale in [MelScale::Htk, MelScale::Slaney] {
+            for f in [0.0, 300.0, 999.0, 1000.0, 1001.0, 8000.0] {
+                assert!((scale.to_hz(scale.to_mel(f)) - f).abs() < 1e-6);
+            }
+        }
+    }
+
+    //每个三角形从左边带的中心开始 到右边带的中心结束 峰在自己的中心
+    #[test]
+    fn filter_edges() {
+        for scale in [MelScale::Htk, MelScale::Slaney] {
+            let settings = MelSettings {
+                bands: 20,
+                min_freq: 100.0,
+                max_freq: 6000.0,
+                scale,
+                log: false,
+            };
+            //一个频点正好1Hz
+            let bank = MelFilterbank::new(settings, 16000, 16000);
+            //两头的边界也是从梅尔换回来的 会差一点点
+            let mut edges = vec![scale.to_hz(scale.to_mel(100.0))];
+            edges.extend(bank.centers().iter().map(|&f| f as f64));
+            edges.push(scale.to_hz(scale.to_mel(6000.0)));
+            for (i, filter) in bank.filters.iter().enumerate() {
+                let (left, center, right) = (edges[i], edges[i + 1], edges[i + 2]);
+                let end = filter.start + filter.weights.len() - 1;
+                assert_eq!(filter.start, left.ceil() as usize, "{scale:?} {i}");
+                assert_eq!(end, right.floor() as usize, "{scale:?} {i}");
+                let peak = filter.start
+                    + (0..filter.weights.len())
+                        .max_by(|&a, &b| filter.weights[a].total_cmp(&filter.weights[b]))
+                        .unwrap();
+                assert!((peak as f64 - center).abs() <= 1.0, "{scale:?} {i}");
+                let top = filter.weights[peak - filter.start] as f64;
+                let sum: f64 = filter.weights.iter().map(|&w| w as f64).sum();
+                match scale {
+                    //HTK峰值是1
+                    MelScale::Htk => assert!((top - 1.0).abs() < 0.1, "{i} {top}"),
+                    //Slaney面积是1
+                    MelScale::Slaney => assert!((sum - 1.0).abs() < 0.05, "{i} {sum}"),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,284 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use realfft::{num_complex::Complex, RealFftPlanner, RealToComplex};
+
+use crate::audio::SpectrumUnit;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MultitaperSettings {
+    pub nw: f32,       //时间带宽积 主瓣半宽是nw个频点(按窗长算)
+    pub tapers: usize, //用几个窗 一般取2nw-1 再多的窗泄漏就大了
+}
+impl Default for MultitaperSettings {
+    fn default() -> Self {
+        Self { nw: 4.0, tapers: 7 }
+    }
+}
+
+/*
+Slepian窗(离散长球序列) 是带宽W内能量最集中的一组正交序列
+它们是下面这个对称三对角矩阵的特征向量 特征值最大的那几个就是要的窗
+对角线 ((L-1-2n)/2)²·cos(2πW) 次对角线 n(L-n)/2
+先用Sturm序列二分找到特征值 再用逆迭代求特征向量 都是O(L)的
+*/
+pub fn dpss(len: usize, nw: f64, count: usize) -> Vec<Vec<f32>> {
+    //窗很长的时候矩阵的特征值挨得太近 直接算会混在一起
+    //Slepian窗很平滑 先算一个短的再线性插值上去
+    if len > MAX_DIRECT_LEN {
+        return dpss(MAX_DIRECT_LEN, nw, count)
+            .into_iter()
+            .map(|taper| interpolate(&taper, len))
+            .collect();
+    }
+    if len <= 1 {
+        return vec![vec![1.0; len]; count.max(1)];
+    }
+    let w = nw / len as f64;
+    let diag: Vec<f64> = (0..len)
+        .map(|n| {
+            let x = (len as f64 - 1.0 - 2.0 * n as f64) / 2.0;
+            x * x * (2.0 * PI * w).cos()
+        })
+        .collect();
+    let off: Vec<f64> = (1..len)
+        .map(|n| n as f64 * (len - n) as f64 / 2.0)
+        .collect();
+    //格什戈林圆 所有特征值都在这个范围里
+    let (low, high) = (0..len).fold((f64::MAX, f64::MIN), |(low, high), i| {
+        let r = if i > 0 { off[i - 1] } else { 0.0 } + off.get(i).copied().unwrap_or(0.0);
+        (low.min(diag[i] - r), high.max(diag[i] + r))
+    });
+    let mut tapers: Vec<Vec<f64>> = Vec::new();
+    for k in 0..count.clamp(1, len) {
+        //第k大的特征值 就是有len-1-k个比它小的
+        let target = len - 1 - k;
+        let (mut a, mut b) = (low, high);
+        for _ in 0..200 {
+            let mid = (a + b) / 2.0;
+            if mid == a || mid == b {
+                break;
+            }
+            if count_below(&diag, &off, mid) > target {
+                b = mid;
+            } else {
+                a = mid;
+            }
+        }
+        let eigenvalue = (a + b) / 2.0;
+        let mut v = inverse_iteration(&diag, &off, eigenvalue, &tapers, k);
+        //和scipy一样定符号 对称的和为正 反对称的一开始往上走
+        let reference: f64 = if k % 2 == 0 {
+            v.iter().sum()
+        } else {
+            v.iter()
+                .enumerate()
+                .map(|(n, x)| (len as f64 - 1.0 - 2.0 * n as f64) * x)
+                .sum()
+        };
+        if reference < 0.0 {
+            v.iter_mut().for_each(|x| *x = -*x);
+        }
+        tapers.push(v);
+    }
+    tapers
+        .into_iter()
+        .map(|v| v.into_iter().map(|x| x as f32).collect())
+        .collect()
+}
+
+//直接解特征向量的最大长度 再长就插值
+const MAX_DIRECT_LEN: usize = 4096;
+
+//线性插值到len个点 再归一化成Σv²=1
+fn interpolate(taper: &[f32], len: usize) -> Vec<f32> {
+    let step = (taper.len() - 1) as f64 / (len - 1) as f64;
+    let mut v: Vec<f64> = (0..len)
+        .map(|n| {
+            let x = n as f64 * step;
+            let i = (x as usize).min(taper.len() - 2);
+            let t = x - i as f64;
+            taper[i] as f64 * (1.0 - t) + taper[i + 1] as f64 * t
+        })
+        .collect();
+    let norm = v.iter().map(|x| x * x).sum::<f64>().sqrt();
+    v.iter_mut().for_each(|x| *x /= norm);
+    v.into_iter().map(|x| x as f32).collect()
+}
+
+//有几个特征值比x小
+fn count_below(diag: &[f64], off: &[f64], x: f64) -> usize {
+    let mut count = 0;
+    let mut q = 1.0;
+    for i in 0..diag.len() {
+        let e2 = if i > 0 { off[i - 1] * off[i - 1] } else { 0.0 };
+        q = diag[i] - x - if i > 0 { e2 / q } else { 0.0 };
+        if q == 0.0 {
+            q = -f64::EPSILON * (diag[i].abs() + e2.sqrt() + 1.0);
+        }
+        if q < 0.0 {
+            count += 1;
+        }
+    }
+    count
+}
+
+//逆迭代 反复解(T-λI)y=x 很快就收敛到λ对应的特征向量
+fn inverse_iteration(
+    diag: &[f64],
+    off: &[f64],
+    eigenvalue: f64,
+    previous: &[Vec<f64>],
+    seed: usize,
+) -> Vec<f64> {
+    let len = diag.len();
+    //稍微偏一点 不然矩阵是奇异的
+    let shift = eigenvalue + (eigenvalue.abs() + 1.0) * 1e-10;
+    //随便一个不太可能和特征向量正交的初值
+    let mut v: Vec<f64> = (0..len)
+        .map(|n| 1.0 + ((n * 7 + seed * 13) % 11) as f64 / 11.0)
+        .collect();
+    for _ in 0..4 {
+        v = solve_tridiagonal(diag, off, shift, &v);
+        //前面的特征向量去掉 挨得近的时候不会收敛到同一个上
+        for p in previous {
+            let dot: f64 = v.iter().zip(p).map(|(a, b)| a * b).sum();
+            v.iter_mut().zip(p).for_each(|(a, b)| *a -= dot * b);
+        }
+        let norm = v.iter().map(|x| x * x).sum::<f64>().sqrt();
+        v.iter_mut().for_each(|x| *x /= norm);
+    }
+    v
+}
+
+//托马斯算法解三对角方程组 主元太小就换成一个小数 逆迭代不在乎这点误差
+fn solve_tridiagonal(diag: &[f64], off: &[f64], shift: f64, rhs: &[f64]) -> Vec<f64> {
+    let len = diag.len();
+    let tiny = f64::EPSILON * (diag.iter().fold(0.0, |m: f64, d| m.max(d.abs())) + 1.0);
+    let mut c = vec![0.0; len];
+    let mut y = vec![0.0; len];
+    let mut pivot = diag[0] - shift;
+    for i in 0..len {
+        if i > 0 {
+            pivot = diag[i] - shift - off[i - 1] * c[i - 1];
+        }
+        if pivot.abs() < tiny {
+            pivot = tiny;
+        }
+        c[i] = off.get(i).copied().unwrap_or(0.0) / pivot;
+        y[i] = (rhs[i] - if i > 0 { off[i - 1] * y[i - 1] } else { 0.0 }) / pivot;
+    }
+    for i in (0..len - 1).rev() {
+        y[i] -= c[i] * y[i + 1];
+    }
+    y
+}
+
+//多窗谱估计 每个Slepian窗各做一次fft 功率谱取平均 方差差不多降到1/K
+pub struct Multitaper {
+    pub settings: MultitaperSettings,
+    pub fftsize: usize,
+    pub window_len: usize,
+    tapers: Vec<Vec<f32>>,
+    gain: f32, //各个窗(Σv)²的平均 校准正弦幅度用 每个窗自己的Σv²都是1
+    plan: Arc<dyn RealToComplex<f32>>,
+    input: Vec<f32>,
+    output: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+}
+impl Multitaper {
+    pub fn new(
+        planner: &mut RealFftPlanner<f32>,
+        settings: MultitaperSettings,
+        fftsize: usize,
+        window_len: usize,
+    ) -> Self {
+        let tapers = dpss(window_len, settings.nw as f64, settings.tapers);
+        let gain = tapers
+            .iter()
+            .map(|t| t.iter().sum::<f32>().powi(2))
+            .sum::<f32>()
+            / tapers.len() as f32;
+        let plan = planner.plan_fft_forward(fftsize);
+        Self {
+            settings,
+            fftsize,
+            window_len,
+            tapers,
+            gain,
+            input: plan.make_input_vec(),
+            output: plan.make_output_vec(),
+            scratch: plan.make_scratch_vec(),
+            plan,
+        }
+    }
+    //和do_fft一样返回校准过的单边功率谱
+    pub fn power_spectrum(
+        &mut self,
+        frame: &[f32],
+        unit: SpectrumUnit,
+        sample_rate: f32,
+    ) -> Vec<f32> {
+        let mut sum = vec![0.0; self.output.len()];
+        for taper in &self.tapers {
+            let (head, tail) = self.input.split_at_mut(taper.len());
+            for ((x, s), w) in head.iter_mut().zip(frame).zip(taper) {
+                *x = s * w;
+            }
+            tail.fill(0.0);
+            self.plan
+                .process_with_scratch(&mut self.input, &mut self.output, &mut self.scratch)
+                .unwrap();
+            for (s, x) in sum.iter_mut().zip(&self.output) {
+                *s += x.norm_sqr();
+            }
+        }
+        //满幅正弦 幅度谱是0dB 功率谱密度按Σv²=1算
+        let scale = match unit {
+            SpectrumUnit::Dbfs => 1.0 / self.gain.max(f32::MIN_POSITIVE),
+            SpectrumUnit::Psd => 1.0 / sample_rate,
+        } / self.tapers.len() as f32;
+        let nyquist = self.fftsize.is_multiple_of(2).then_some(sum.len() - 1);
+        sum.into_iter()
+            .enumerate()
+            .map(|(k, p)| {
+                let fold = if k == 0 || Some(k) == nyquist {
+                    1.0
+                } else {
+                    4.0
+                };
+                p * fold * scale
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dot(a: &[f32], b: &[f32]) -> f64 {
+        a.iter().zip(b).map(|(&a, &b)| a as f64 * b as f64).sum()
+    }
+
+    //Slepian窗两两正交 每个的平方和是1 偶数号的对称 奇数号的反对称
+    #[test]
+    fn dpss_are_orthonormal() {
+        //8192的是插值出来的 只能差不多正交
+        for (len, tolerance) in [(64, 1e-5), (1000, 1e-5), (8192, 1e-3)] {
+            let tapers = dpss(len, 4.0, 7);
+            assert_eq!(tapers.len(), 7);
+            for (i, a) in tapers.iter().enumerate() {
+                assert_eq!(a.len(), len);
+                for (j, b) in tapers.iter().enumerate() {
+                    let expected = if i == j { 1.0 } else { 0.0 };
+                    let d = dot(a, b);
+                    assert!((d - expected).abs() < tolerance, "{len} {i} {j} {d}");
+                }
+                let sign = if i % 2 == 0 { 1.0 } else { -1.0 };
+                for n in 0..len / 2 {
+                    assert!((a[n] - sign * a[len - 1 - n]).abs() < 1e-4, "{len} {i} {n}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,389 @@
+use std::{
+    io::{ErrorKind, Read},
+    net::{TcpListener, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use crate::{audio::AudioSource, pcm::RawFormat};
+
+/*
+每个包前面有20字节的头 全部是小端
+  0  4  魔数 "SPCM"
+  4  4  序号 每包加一 用来发现丢包
+  8  4  采样率
+ 12  2  声道数
+ 14  1  采样格式 RawFormat的值 见pcm.rs
+ 15  1  保留
+ 16  4  后面PCM数据的字节数
+后面跟着交错的原始PCM UDP一个数据报一个包 TCP就是包一个接一个
+*/
+pub const HEADER_SIZE: usize = 20;
+pub const MAGIC: &[u8; 4] = b"SPCM";
+//丢包时最多补多少个包的静音 免得序号乱跳的时候一下补出一大段
+const MAX_FILL_PACKETS: u32 = 64;
+//一个包最多多大 头里的长度超过这个就当成坏数据 TCP直接断开
+const MAX_PAYLOAD: usize = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protocol {
+    Udp,
+    Tcp,
+}
+
+struct Header {
+    seq: u32,
+    rate: u32,
+    channels: usize,
+    format: RawFormat,
+    length: usize,
+}
+impl Header {
+    fn parse(b: &[u8]) -> Option<Self> {
+        if b.len() < HEADER_SIZE || &b[0..4] != MAGIC {
+            return None;
+        }
+        let u32_at = |i: usize| u32::from_le_bytes([b[i], b[i + 1], b[i + 2], b[i + 3]]);
+        let (rate, length) = (u32_at(8), u32_at(16) as usize);
+        if rate == 0 || length > MAX_PAYLOAD {
+            return None;
+        }
+        Some(Self {
+            seq: u32_at(4),
+            rate,
+            channels: u16::from_le_bytes([b[12], b[13]]).max(1) as usize,
+            format: RawFormat::from_code(b[14])?,
+            length,
+        })
+    }
+}
+
+//收包的统计 给界面显示
+#[derive(Default)]
+struct Stats {
+    packets: AtomicU64,
+    lost: AtomicU64,
+    late: AtomicU64, //乱序晚到被丢掉的
+}
+
+//在UDP或TCP端口上收带头的原始PCM
+pub struct NetworkSource {
+    protocol: Protocol,
+    port: u16,
+    sample_rate: Arc<AtomicU32>,
+    channels: Arc<AtomicUsize>,
+    stats: Arc<Stats>,
+    paused: Arc<AtomicBool>,
+    quit: Arc<AtomicBool>,
+    error: Arc<Mutex<Option<String>>>, //收包线程出错退出的原因
+    thread: Option<JoinHandle<()>>,
+}
+impl NetworkSource {
+    pub fn new(protocol: Protocol, port: u16) -> Self {
+        Self {
+            protocol,
+            port,
+            //收到第一个包之前先假设一个 收到以后按包头里的来
+            sample_rate: Arc::new(AtomicU32::new(48000)),
+            channels: Arc::new(AtomicUsize::new(1)),
+            stats: Arc::new(Stats::default()),
+            paused: Arc::new(AtomicBool::new(false)),
+            quit: Arc::new(AtomicBool::new(false)),
+            error: Arc::new(Mutex::new(None)),
+            thread: None,
+        }
+    }
+}
+
+//收包线程里的状态
+struct Receiver {
+    sample_rate: Arc<AtomicU32>,
+    channels: Arc<AtomicUsize>,
+    stats: Arc<Stats>,
+    paused: Arc<AtomicBool>,
+    quit: Arc<AtomicBool>,
+    tx: mpsc::Sender<Vec<f32>>,
+    expected: Option<u32>, //下一个应该收到的序号
+}
+impl Receiver {
+    //处理一个完整的包 接收端没了返回false
+    fn handle(&mut self, header: &Header, payload: &[u8]) -> bool {
+        self.stats.packets.fetch_add(1, Ordering::Relaxed);
+        let mut data = Vec::new();
+        if let Some(expected) = self.expected {
+            let gap = header.seq.wrapping_sub(expected);
+            if gap > u32::MAX / 2 {
+                //比期望的序号小 是晚到的包 直接丢掉
+                self.stats.late.fetch_add(1, Ordering::Relaxed);
+                return true;
+            }
+            if gap > 0 {
+                self.stats.lost.fetch_add(gap as u64, Ordering::Relaxed);
+                //丢掉的包用同样长度的静音补上 时间轴才不会错位
+                let samples = payload.len() / header.format.size();
+                data.resize(samples * gap.min(MAX_FILL_PACKETS) as usize, 0.0);
+            }
+        }
+        self.expected = Some(header.seq.wrapping_add(1));
+        self.sample_rate.store(header.rate, Ordering::Relaxed);
+        self.channels.store(header.channels, Ordering::Relaxed);
+        if self.paused.load(Ordering::Relaxed) {
+            return true;
+        }
+        //只要完整的帧
+        let frame = header.format.size() * header.channels;
+        let payload = &payload[..payload.len() / frame * frame];
+        data.extend(header.format.decode(payload));
+        self.tx.send(data).is_ok()
+    }
+    fn run_udp(mut self, socket: UdpSocket) -> Result<(), anyhow::Error> {
+        let mut buffer = vec![0u8; 65536];
+        while !self.quit.load(Ordering::Relaxed) {
+            let n = match socket.recv(&mut buffer) {
+                Ok(n) => n,
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    continue
+                }
+                Err(e) => return Err(e.into()),
+            };
+            let Some(header) = Header::parse(&buffer[..n]) else {
+                continue;
+            };
+            let end = (HEADER_SIZE + header.length).min(n);
+            if !self.handle(&header, &buffer[HEADER_SIZE..end]) {
+                break;
+            }
+        }
+        Ok(())
+    }
+    fn run_tcp(mut self, listener: TcpListener) -> Result<(), anyhow::Error> {
+        while !self.quit.load(Ordering::Relaxed) {
+            let mut stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    std::thread::sleep(Duration::from_millis(200));
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            stream.set_nonblocking(false)?;
+            stream.set_read_timeout(Some(Duration::from_millis(200)))?;
+            //新连接的序号重新开始算
+            self.expected = None;
+            let mut header = [0u8; HEADER_SIZE];
+            let mut payload = Vec::new();
+            loop {
+                if self.quit.load(Ordering::Relaxed) {
+                    return Ok(());
+                }
+                match self.read_full(&mut stream, &mut header) {
+                    Ok(true) => {}
+                    //对面断开了 等下一个连接
+                    Ok(false) | Err(_) => break,
+                }
+                let Some(h) = Header::parse(&header) else {
+                    //流已经错位了或者头里的数不对 没法再找到包的边界 断开让对面重连
+                    break;
+                };
+                payload.resize(h.length, 0);
+                if !matches!(self.read_full(&mut stream, &mut payload), Ok(true)) {
+                    break;
+                }
+                if !self.handle(&h, &payload) {
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+    //读满整个缓冲区 超时就继续等 对面关闭返回false
+    fn read_full(&self, stream: &mut impl Read, buffer: &mut [u8]) -> Result<bool, anyhow::Error> {
+        let mut filled = 0;
+        while filled < buffer.len() {
+            if self.quit.load(Ordering::Relaxed) {
+                return Ok(false);
+            }
+            match stream.read(&mut buffer[filled..]) {
+                Ok(0) => return Ok(false),
+                Ok(n) => filled += n,
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(true)
+    }
+}
+impl AudioSource for NetworkSource {
+    fn start(&mut self, tx: mpsc::Sender<Vec<f32>>) -> Result<(), anyhow::Error> {
+        self.paused.store(false, Ordering::Relaxed);
+        //线程还在就接着用 出错退出了就重新绑定
+        if self.thread.as_ref().is_some_and(|t| !t.is_finished()) {
+            return Ok(());
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        *self.error.lock().unwrap() = None;
+        let receiver = Receiver {
+            sample_rate: self.sample_rate.clone(),
+            channels: self.channels.clone(),
+            stats: self.stats.clone(),
+            paused: self.paused.clone(),
+            quit: self.quit.clone(),
+            tx,
+            expected: None,
+        };
+        //在这里绑定 端口被占用之类的错误直接报出来 绑好的交给线程
+        let address = ("0.0.0.0", self.port);
+        let run: Box<dyn FnOnce() -> Result<(), anyhow::Error> + Send> = match self.protocol {
+            Protocol::Udp => {
+                let socket = UdpSocket::bind(address)?;
+                //带超时 好让线程能看到退出标志
+                socket.set_read_timeout(Some(Duration::from_millis(200)))?;
+                Box::new(move || receiver.run_udp(socket))
+            }
+            Protocol::Tcp => {
+                let listener = TcpListener::bind(address)?;
+                listener.set_nonblocking(true)?;
+                Box::new(move || receiver.run_tcp(listener))
+            }
+        };
+        let error = self.error.clone();
+        self.thread = Some(std::thread::spawn(move || {
+            if let Err(e) = run() {
+                *error.lock().unwrap() = Some(e.to_string());
+            }
+        }));
+        Ok(())
+    }
+    fn stop(&mut self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate.load(Ordering::Relaxed)
+    }
+    fn channels(&self) -> usize {
+        self.channels.load(Ordering::Relaxed)
+    }
+    fn status(&self) -> Option<String> {
+        if let Some(e) = &*self.error.lock().unwrap() {
+            return Some(format!("收包出错: {e}"));
+        }
+        Some(format!(
+            "已收 {} 包  丢失 {} 包  乱序 {} 包",
+            self.stats.packets.load(Ordering::Relaxed),
+            self.stats.lost.load(Ordering::Relaxed),
+            self.stats.late.load(Ordering::Relaxed)
+        ))
+    }
+}
+impl Drop for NetworkSource {
+    fn drop(&mut self) {
+        self.quit.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(seq: u32, rate: u32, format: RawFormat, length: u32) -> Vec<u8> {
+        let mut b = Vec::new();
+        b.extend_from_slice(MAGIC);
+        b.extend_from_slice(&seq.to_le_bytes());
+        b.extend_from_slice(&rate.to_le_bytes());
+        b.extend_from_slice(&2u16.to_le_bytes());
+        b.push(format as u8);
+        b.push(0);
+        b.extend_from_slice(&length.to_le_bytes());
+        b
+    }
+
+    #[test]
+    fn parse_reads_every_field() {
+        let h = Header::parse(&header(7, 44100, RawFormat::F32LE, 64)).unwrap();
+        assert_eq!(h.seq, 7);
+        assert_eq!(h.rate, 44100);
+        assert_eq!(h.channels, 2);
+        assert_eq!(h.format, RawFormat::F32LE);
+        assert_eq!(h.length, 64);
+    }
+
+    #[test]
+    fn parse_rejects_bad_headers() {
+        let good = header(0, 48000, RawFormat::S16LE, 64);
+        assert!(Header::parse(&good[..HEADER_SIZE - 1]).is_none(), "太短");
+        let mut magic = good.clone();
+        magic[0] = b'X';
+        assert!(Header::parse(&magic).is_none(), "魔数不对");
+        let mut format = good.clone();
+        format[14] = 200;
+        assert!(Header::parse(&format).is_none(), "不认识的格式");
+        assert!(
+            Header::parse(&header(0, 0, RawFormat::S16LE, 64)).is_none(),
+            "采样率是0"
+        );
+        let oversized = header(0, 48000, RawFormat::S16LE, MAX_PAYLOAD as u32 + 1);
+        assert!(Header::parse(&oversized).is_none(), "长度太大");
+        let largest = header(0, 48000, RawFormat::S16LE, MAX_PAYLOAD as u32);
+        assert!(Header::parse(&largest).is_some());
+    }
+
+    fn receiver() -> (Receiver, mpsc::Receiver<Vec<f32>>, Arc<Stats>) {
+        let (tx, rx) = mpsc::channel();
+        let stats = Arc::new(Stats::default());
+        let receiver = Receiver {
+            sample_rate: Arc::new(AtomicU32::new(48000)),
+            channels: Arc::new(AtomicUsize::new(1)),
+            stats: stats.clone(),
+            paused: Arc::new(AtomicBool::new(false)),
+            quit: Arc::new(AtomicBool::new(false)),
+            tx,
+            expected: None,
+        };
+        (receiver, rx, stats)
+    }
+
+    //一个双声道s16le的包 两帧都是满幅的一半
+    fn packet(receiver: &mut Receiver, seq: u32) -> bool {
+        let payload: Vec<u8> = [16384i16; 4].iter().flat_map(|s| s.to_le_bytes()).collect();
+        let h = Header::parse(&header(seq, 48000, RawFormat::S16LE, 8)).unwrap();
+        receiver.handle(&h, &payload)
+    }
+
+    //丢了的包用同样长度的静音补上 晚到的包丢掉
+    #[test]
+    fn lost_packets_are_filled_with_silence() {
+        let (mut receiver, rx, stats) = receiver();
+        packet(&mut receiver, 0);
+        assert_eq!(rx.try_recv().unwrap(), vec![0.5; 4]);
+        packet(&mut receiver, 3);
+        let mut expected = vec![0.0; 8];
+        expected.extend([0.5; 4]);
+        assert_eq!(rx.try_recv().unwrap(), expected);
+        assert_eq!(stats.lost.load(Ordering::Relaxed), 2);
+        packet(&mut receiver, 1);
+        assert!(rx.try_recv().is_err());
+        assert_eq!(stats.late.load(Ordering::Relaxed), 1);
+        assert_eq!(stats.packets.load(Ordering::Relaxed), 3);
+    }
+
+    //序号一下跳很远的时候最多补MAX_FILL_PACKETS个包
+    #[test]
+    fn fill_is_capped() {
+        let (mut receiver, rx, stats) = receiver();
+        packet(&mut receiver, 0);
+        rx.try_recv().unwrap();
+        packet(&mut receiver, 10_000);
+        let data = rx.try_recv().unwrap();
+        assert_eq!(data.len(), (MAX_FILL_PACKETS as usize + 1) * 4);
+        assert_eq!(stats.lost.load(Ordering::Relaxed), 9_999);
+    }
+}
@@ -0,0 +1,145 @@
+//examples/pcm_sender.rs也直接引用这个文件 所以这里不能用crate里别的东西
+
+//原始PCM的样本格式 名字和sox/ffmpeg/arecord的写法一致
+//网络包头里的格式字节就是这里的值 只能往后加 不能改
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum RawFormat {
+    U8 = 0,
+    S8 = 1,
+    S16LE = 2,
+    S16BE = 3,
+    S24LE = 4,
+    S24BE = 5,
+    S32LE = 6,
+    S32BE = 7,
+    F32LE = 8,
+    F32BE = 9,
+    F64LE = 10,
+    F64BE = 11,
+}
+impl RawFormat {
+    pub const ALL: [RawFormat; 12] = [
+        RawFormat::U8,
+        RawFormat::S8,
+        RawFormat::S16LE,
+        RawFormat::S16BE,
+        RawFormat::S24LE,
+        RawFormat::S24BE,
+        RawFormat::S32LE,
+        RawFormat::S32BE,
+        RawFormat::F32LE,
+        RawFormat::F32BE,
+        RawFormat::F64LE,
+        RawFormat::F64BE,
+    ];
+    pub fn name(&self) -> &'static str {
+        match self {
+            RawFormat::U8 => "u8",
+            RawFormat::S8 => "s8",
+            RawFormat::S16LE => "s16le",
+            RawFormat::S16BE => "s16be",
+            RawFormat::S24LE => "s24le",
+            RawFormat::S24BE => "s24be",
+            RawFormat::S32LE => "s32le",
+            RawFormat::S32BE => "s32be",
+            RawFormat::F32LE => "f32le",
+            RawFormat::F32BE => "f32be",
+            RawFormat::F64LE => "f64le",
+            RawFormat::F64BE => "f64be",
+        }
+    }
+    //包头里的格式字节转回来 不认识的返回None
+    pub fn from_code(code: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|f| *f as u8 == code)
+    }
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|f| f.name().eq_ignore_ascii_case(name))
+    }
+    //一个样本占几个字节
+    pub fn size(&self) -> usize {
+        match self {
+            RawFormat::U8 | RawFormat::S8 => 1,
+            RawFormat::S16LE | RawFormat::S16BE => 2,
+            RawFormat::S24LE | RawFormat::S24BE => 3,
+            RawFormat::S32LE | RawFormat::S32BE | RawFormat::F32LE | RawFormat::F32BE => 4,
+            RawFormat::F64LE | RawFormat::F64BE => 8,
+        }
+    }
+    //把一个样本的字节转换成-1到1的f32
+    fn sample(&self, b: &[u8]) -> f32 {
+        match self {
+            RawFormat::U8 => (b[0] as f32 - 128.0) / 128.0,
+            RawFormat::S8 => b[0] as i8 as f32 / 128.0,
+            RawFormat::S16LE => i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0,
+            RawFormat::S16BE => i16::from_be_bytes([b[0], b[1]]) as f32 / 32768.0,
+            //24位的放到i32的高三个字节里 符号位就对了
+            RawFormat::S24LE => i32::from_le_bytes([0, b[0], b[1], b[2]]) as f32 / 2147483648.0,
+            RawFormat::S24BE => i32::from_be_bytes([b[0], b[1], b[2], 0]) as f32 / 2147483648.0,
+            RawFormat::S32LE => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2147483648.0,
+            RawFormat::S32BE => i32::from_be_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2147483648.0,
+            RawFormat::F32LE => f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            RawFormat::F32BE => f32::from_be_bytes([b[0], b[1], b[2], b[3]]),
+            RawFormat::F64LE => f64::from_le_bytes(b[..8].try_into().unwrap()) as f32,
+            RawFormat::F64BE => f64::from_be_bytes(b[..8].try_into().unwrap()) as f32,
+        }
+    }
+    //把整段字节转换成f32 末尾不够一个样本的部分忽略
+    pub fn decode(&self, bytes: &[u8]) -> Vec<f32> {
+        bytes
+            .chunks_exact(self.size())
+            .map(|b| self.sample(b))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //每种格式都是0.5和-1 多字节的后面再多一个不够一个样本的字节
+    #[test]
+    fn decode_every_format() {
+        let cases: [(RawFormat, &[u8]); 12] = [
+            (RawFormat::U8, &[0xC0, 0x00]),
+            (RawFormat::S8, &[0x40, 0x80]),
+            (RawFormat::S16LE, &[0x00, 0x40, 0x00, 0x80]),
+            (RawFormat::S16BE, &[0x40, 0x00, 0x80, 0x00]),
+            (RawFormat::S24LE, &[0x00, 0x00, 0x40, 0x00, 0x00, 0x80]),
+            (RawFormat::S24BE, &[0x40, 0x00, 0x00, 0x80, 0x00, 0x00]),
+            (RawFormat::S32LE, &[0, 0, 0, 0x40, 0, 0, 0, 0x80]),
+            (RawFormat::S32BE, &[0x40, 0, 0, 0, 0x80, 0, 0, 0]),
+            (RawFormat::F32LE, &[0, 0, 0, 0x3F, 0, 0, 0x80, 0xBF]),
+            (RawFormat::F32BE, &[0x3F, 0, 0, 0, 0xBF, 0x80, 0, 0]),
+            (
+                RawFormat::F64LE,
+                &[0, 0, 0, 0, 0, 0, 0xE0, 0x3F, 0, 0, 0, 0, 0, 0, 0xF0, 0xBF],
+            ),
+            (
+                RawFormat::F64BE,
+                &[0x3F, 0xE0, 0, 0, 0, 0, 0, 0, 0xBF, 0xF0, 0, 0, 0, 0, 0, 0],
+            ),
+        ];
+        for (format, bytes) in cases {
+            let mut bytes = bytes.to_vec();
+            if format.size() > 1 {
+                bytes.push(0x7F);
+            }
+            assert_eq!(format.decode(&bytes), [0.5, -1.0], "{}", format.name());
+        }
+    }
+
+    //名字和包头里的字节都能转回来
+    #[test]
+    fn names_and_codes_round_trip() {
+        for format in RawFormat::ALL {
+            assert_eq!(RawFormat::parse(format.name()), Some(format));
+            assert_eq!(RawFormat::from_code(format as u8), Some(format));
+        }
+        assert_eq!(RawFormat::parse("S16LE"), Some(RawFormat::S16LE));
+        assert_eq!(RawFormat::parse("s16"), None);
+        assert_eq!(RawFormat::from_code(RawFormat::ALL.len() as u8), None);
+    }
+}
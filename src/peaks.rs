@@ -0,0 +1,144 @@
+//峰值在两个频点之间的位置怎么插值
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interpolation {
+    Parabolic, //在线性幅度上拟合抛物线
+    Gaussian,  //在dB上拟合抛物线 相当于对幅度拟合高斯 汉宁这类窗更准
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PeakSettings {
+    pub threshold: f32,  //低于这个dB的不算
+    pub prominence: f32, //比两边的谷至少高出多少dB
+    pub count: usize,    //最多留几个 按电平从高到低
+    pub interpolation: Interpolation,
+}
+impl Default for PeakSettings {
+    fn default() -> Self {
+        Self {
+            threshold: -80.0,
+            prominence: 10.0,
+            count: 5,
+            interpolation: Interpolation::Gaussian,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Peak {
+    pub bin: f32,   //插值过的 带小数
+    pub level: f32, //插值过的 dB
+}
+
+/*
+在一列dB频谱上找峰
+先找比左右都高的点 再算突出度: 往两边走到第一个比它高的点为止 路上的最低点是谷
+两边的谷取高的那个 峰比它高出多少就是突出度 和scipy的find_peaks一样
+*/
+pub fn find_peaks(spectrum: &[f32], settings: &PeakSettings) -> Vec<Peak> {
+    let n = spectrum.len();
+    let mut peaks: Vec<Peak> = (1..n.saturating_sub(1))
+        .filter(|&k| {
+            let x = spectrum[k];
+            //平顶的峰只算最左边那个点
+            x >= settings.threshold && x > spectrum[k - 1] && x >= spectrum[k + 1]
+        })
+        .filter(|&k| prominence(spectrum, k) >= settings.prominence)
+        .map(|k| interpolate(spectrum, k, settings.interpolation))
+        .collect();
+    peaks.sort_by(|a, b| b.level.total_cmp(&a.level));
+    peaks.truncate(settings.count);
+    peaks
+}
+
+fn prominence(spectrum: &[f32], k: usize) -> f32 {
+    let x = spectrum[k];
+    let valley = |range: &mut dyn Iterator<Item = usize>| {
+        let mut min = x;
+        for i in range {
+            if spectrum[i] > x {
+                break;
+            }
+            min = min.min(spectrum[i]);
+        }
+        min
+    };
+    let left = valley(&mut (0..k).rev());
+    let right = valley(&mut (k + 1..spectrum.len()));
+    x - left.max(right)
+}
+
+//用峰和左右两个点拟合抛物线 顶点就是真正的峰
+fn interpolate(spectrum: &[f32], k: usize, interpolation: Interpolation) -> Peak {
+    let (a, b, c) = (spectrum[k - 1], spectrum[k], spectrum[k + 1]);
+    let (a, b, c) = match interpolation {
+        Interpolation::Parabolic => (
+            10f32.powf(a / 20.0),
+            10f32.powf(b / 20.0),
+            10f32.powf(c / 20.0),
+        ),
+        Interpolation::Gaussian => (a, b, c),
+    };
+    let denominator = a - 2.0 * b + c;
+    if denominator >= 0.0 {
+        return Peak {
+            bin: k as f32,
+            level: spectrum[k],
+        };
+    }
+    let offset = (0.5 * (a - c) / denominator).clamp(-0.5, 0.5);
+    let top = b - 0.25 * (a - c) * offset;
+    let level = match interpolation {
+        Interpolation::Parabolic => 20.0 * (top + 1e-20).log10(),
+        Interpolation::Gaussian => top,
+    };
+    Peak {
+        bin: k as f32 + offset,
+        level,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //和scipy.signal.peak_prominences一样的例子
+    #[test]
+    fn prominence_stops_at_higher_ground() {
+        let spectrum = [0.0, 10.0, 2.0, 6.0, 1.0, 20.0, 0.0];
+        assert_eq!(prominence(&spectrum, 1), 9.0);
+        assert_eq!(prominence(&spectrum, 3), 4.0);
+        assert_eq!(prominence(&spectrum, 5), 20.0);
+        let settings = PeakSettings {
+            threshold: 5.0,
+            prominence: 5.0,
+            count: 5,
+            interpolation: Interpolation::Gaussian,
+        };
+        //6那个不够突出 按电平从高到低排
+        let bins: Vec<f32> = find_peaks(&spectrum, &settings)
+            .iter()
+            .map(|p| p.bin.round())
+            .collect();
+        assert_eq!(bins, vec![5.0, 1.0]);
+        let settings = PeakSettings {
+            threshold: 15.0,
+            ..settings
+        };
+        assert_eq!(find_peaks(&spectrum, &settings).len(), 1);
+    }
+
+    //dB上的抛物线用高斯插值 线性幅度上的抛物线用抛物线插值 都应该正好插到顶点
+    #[test]
+    fn interpolation_finds_the_vertex() {
+        let gaussian: Vec<f32> = (0..20).map(|k| -3.0 * (k as f32 - 10.3).powi(2)).collect();
+        let peak = interpolate(&gaussian, 10, Interpolation::Gaussian);
+        assert!((peak.bin - 10.3).abs() < 1e-4, "{peak:?}");
+        assert!(peak.level.abs() < 1e-3, "{peak:?}");
+        let parabola: Vec<f32> = (0..20)
+            .map(|k| 20.0 * (1.0 - 0.01 * (k as f32 - 9.8).powi(2)).log10())
+            .collect();
+        let peak = interpolate(&parabola, 10, Interpolation::Parabolic);
+        assert!((peak.bin - 9.8).abs() < 1e-4, "{peak:?}");
+        assert!(peak.level.abs() < 1e-3, "{peak:?}");
+    }
+}
@@ -0,0 +1,190 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{ErrorKind, Read},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use crate::{audio::AudioSource, pcm::RawFormat};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PipeInput {
+    Stdin,
+    Fifo(PathBuf), //命名管道 或者别的能一直读的文件
+}
+
+//从标准输入或者命名管道读交错的原始PCM
+//同一个输入只能有一个线程在读 所以重新开始的时候界面会接着用原来的PipeSource
+pub struct PipeSource {
+    input: PipeInput,
+    format: RawFormat,
+    sample_rate: u32,
+    channels: usize,
+    paused: Arc<AtomicBool>,
+    quit: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+impl PipeSource {
+    pub fn new(input: PipeInput, format: RawFormat, sample_rate: u32, channels: usize) -> Self {
+        Self {
+            input,
+            format,
+            sample_rate,
+            channels: channels.max(1),
+            paused: Arc::new(AtomicBool::new(false)),
+            quit: Arc::new(AtomicBool::new(false)),
+            thread: None,
+        }
+    }
+    //命名管道用非阻塞的方式打开 没人写的时候open不会卡住 读的时候也能看到退出标志
+    fn open(path: &Path) -> Result<File, std::io::Error> {
+        let mut options = OpenOptions::new();
+        options.read(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.custom_flags(libc::O_NONBLOCK);
+        }
+        options.open(path)
+    }
+    fn run(
+        mut reader: Box<dyn Read + Send>,
+        format: RawFormat,
+        channels: usize,
+        paused: Arc<AtomicBool>,
+        quit: Arc<AtomicBool>,
+        tx: mpsc::Sender<Vec<f32>>,
+        fifo: bool,
+    ) {
+        let frame = format.size() * channels;
+        let mut buffer = vec![0u8; frame * 1024];
+        let mut filled = 0;
+        while !quit.load(Ordering::Relaxed) {
+            let n = match reader.read(&mut buffer[filled..]) {
+                //命名管道的写端关了 等下一个来写的 标准输入读到头就是真的完了
+                Ok(0) if fifo => {
+                    std::thread::sleep(Duration::from_millis(20));
+                    continue;
+                }
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    std::thread::sleep(Duration::from_millis(5));
+                    continue;
+                }
+                Err(_) => break,
+            };
+            filled += n;
+            //只处理完整的帧 剩下的留到下一次
+            let whole = filled / frame * frame;
+            //暂停的时候照样读 不然写的那一端会被堵住
+            if !paused.load(Ordering::Relaxed)
+                && whole > 0
+                && tx.send(format.decode(&buffer[..whole])).is_err()
+            {
+                break;
+            }
+            buffer.copy_within(whole..filled, 0);
+            filled -= whole;
+        }
+    }
+}
+impl AudioSource for PipeSource {
+    fn start(&mut self, tx: mpsc::Sender<Vec<f32>>) -> Result<(), anyhow::Error> {
+        self.paused.store(false, Ordering::Relaxed);
+        if self.thread.is_some() {
+            return Ok(());
+        }
+        let (reader, fifo): (Box<dyn Read + Send>, bool) = match &self.input {
+            PipeInput::Stdin => (Box::new(std::io::stdin()), false),
+            PipeInput::Fifo(path) => (Box::new(Self::open(path)?), true),
+        };
+        let (format, channels) = (self.format, self.channels);
+        let (paused, quit) = (self.paused.clone(), self.quit.clone());
+        self.thread = Some(std::thread::spawn(move || {
+            PipeSource::run(reader, format, channels, paused, quit, tx, fifo)
+        }));
+        Ok(())
+    }
+    fn stop(&mut self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+    fn channels(&self) -> usize {
+        self.channels
+    }
+}
+impl Drop for PipeSource {
+    fn drop(&mut self) {
+        self.quit.store(true, Ordering::Relaxed);
+        //标准输入的读是阻塞的 没法打断 等下一块数据来了线程自己会退出 这里不等它
+        if matches!(self.input, PipeInput::Fifo(_)) {
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{ffi::CString, io::Write, os::unix::ffi::OsStrExt, time::Instant};
+
+    use super::*;
+
+    fn fifo(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let c = CString::new(path.as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(c.as_ptr(), 0o600) }, 0);
+        path
+    }
+
+    //没人写的命名管道 开始不会卡住 丢掉的时候线程也能退出
+    #[test]
+    fn fifo_without_writer_does_not_block() {
+        let path = fifo("pipe-no-writer");
+        let (tx, _rx) = mpsc::channel();
+        let mut source = PipeSource::new(PipeInput::Fifo(path.clone()), RawFormat::S16LE, 48000, 1);
+        let begin = Instant::now();
+        source.start(tx).unwrap();
+        drop(source);
+        assert!(begin.elapsed() < Duration::from_secs(1));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    //写端中途关掉再来一个 还是接着读 帧不会错位
+    #[test]
+    fn fifo_keeps_reading_across_writers() {
+        let path = fifo("pipe-writers");
+        let (tx, rx) = mpsc::channel();
+        let mut source = PipeSource::new(PipeInput::Fifo(path.clone()), RawFormat::S16LE, 48000, 2);
+        source.start(tx).unwrap();
+        let mut received = Vec::new();
+        for value in [8192i16, -16384] {
+            let mut writer = OpenOptions::new().write(true).open(&path).unwrap();
+            //一帧拆成两次写 第二次写之前读线程可能已经读到半帧
+            let bytes: Vec<u8> = [value; 4].iter().flat_map(|s| s.to_le_bytes()).collect();
+            writer.write_all(&bytes[..3]).unwrap();
+            writer.flush().unwrap();
+            std::thread::sleep(Duration::from_millis(30));
+            writer.write_all(&bytes[3..]).unwrap();
+            drop(writer);
+            while received.len() < 4 {
+                received.extend(rx.recv_timeout(Duration::from_secs(2)).unwrap());
+            }
+            assert_eq!(received, vec![value as f32 / 32768.0; 4]);
+            received.clear();
+        }
+        drop(source);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,284 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use crate::audio::AudioSource;
+
+//能按帧读取的数据 文件解码器、信号发生器之类的实现这个 交给PlaybackSource按实时速度播放
+pub trait FrameReader: Send {
+    //最多读frames帧 返回交错的数据 读到结尾返回空
+    fn read(&mut self, frames: usize) -> Result<Vec<f32>, anyhow::Error>;
+    fn seek(&mut self, frame: u64) -> Result<(), anyhow::Error>;
+    fn sample_rate(&self) -> u32;
+    fn channels(&self) -> usize;
+    //总帧数 无限长的返回None
+    fn length(&self) -> Option<u64>;
+}
+
+//播放控制 界面线程和播放线程共享
+pub struct Transport {
+    playing: AtomicBool,
+    looping: AtomicBool,
+    position: AtomicU64,
+    seek_to: Mutex<Option<u64>>,
+    length: Option<u64>,
+    //解码器可能要解了第一块才知道真正的格式 播放线程每读一块就更新一次
+    sample_rate: AtomicU32,
+    channels: AtomicUsize,
+    error: Mutex<Option<String>>,
+}
+impl Transport {
+    fn new(reader: &dyn FrameReader) -> Self {
+        Self {
+            playing: AtomicBool::new(true),
+            looping: AtomicBool::new(false),
+            position: AtomicU64::new(0),
+            seek_to: Mutex::new(None),
+            length: reader.length(),
+            sample_rate: AtomicU32::new(reader.sample_rate()),
+            channels: AtomicUsize::new(reader.channels()),
+            error: Mutex::new(None),
+        }
+    }
+    pub fn is_playing(&self) -> bool {
+        self.playing.load(Ordering::Relaxed)
+    }
+    pub fn set_playing(&self, playing: bool) {
+        //已经放完了再点播放就从头开始
+        if playing
+            && self
+                .length
+                .is_some_and(|l| self.position.load(Ordering::Relaxed) >= l)
+        {
+            self.seek(0.0);
+        }
+        self.playing.store(playing, Ordering::Relaxed)
+    }
+    pub fn is_looping(&self) -> bool {
+        self.looping.load(Ordering::Relaxed)
+    }
+    pub fn set_looping(&self, looping: bool) {
+        self.looping.store(looping, Ordering::Relaxed)
+    }
+    //当前播放到的秒数
+    pub fn position(&self) -> f64 {
+        self.position.load(Ordering::Relaxed) as f64 / self.sample_rate() as f64
+    }
+    //总时长 无限长的返回None
+    pub fn duration(&self) -> Option<f64> {
+        Some(self.length? as f64 / self.sample_rate() as f64)
+    }
+    //跳到指定的秒数 真正的跳转在播放线程里做
+    pub fn seek(&self, seconds: f64) {
+        let frame = (seconds.max(0.0) * self.sample_rate() as f64) as u64;
+        *self.seek_to.lock().unwrap() = Some(frame);
+        self.position.store(frame, Ordering::Relaxed);
+    }
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate.load(Ordering::Relaxed).max(1)
+    }
+    //播放线程出错时的信息
+    pub fn error(&self) -> Option<String> {
+        self.error.lock().unwrap().clone()
+    }
+}
+
+//把FrameReader按实时速度推送出去的音源
+pub struct PlaybackSource {
+    reader: Option<Box<dyn FrameReader>>,
+    transport: Arc<Transport>,
+    quit: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+impl PlaybackSource {
+    pub fn new(reader: Box<dyn FrameReader>) -> Self {
+        let transport = Arc::new(Transport::new(reader.as_ref()));
+        Self {
+            reader: Some(reader),
+            transport,
+            quit: Arc::new(AtomicBool::new(false)),
+            thread: None,
+        }
+    }
+    fn run(
+        mut reader: Box<dyn FrameReader>,
+        transport: Arc<Transport>,
+        quit: Arc<AtomicBool>,
+        tx: mpsc::Sender<Vec<f32>>,
+    ) -> Result<(), anyhow::Error> {
+        let mut next = Instant::now();
+        //刚从头开始读 这时候还读不到东西就是空文件 再循环也是空转
+        let mut rewound = false;
+        while !quit.load(Ordering::Relaxed) {
+            //每次推10毫秒的数据
+            let sample_rate = reader.sample_rate().max(1);
+            let block = (sample_rate as usize / 100).max(1);
+            let block_time = Duration::from_secs_f64(block as f64 / sample_rate as f64);
+            if let Some(frame) = transport.seek_to.lock().unwrap().take() {
+                reader.seek(frame)?;
+            }
+            if !transport.is_playing() {
+                std::thread::sleep(block_time);
+                next = Instant::now();
+                continue;
+            }
+            let data = reader.read(block)?;
+            if data.is_empty() {
+                //放完了 循环的话从头再来 不然就停在结尾
+                if transport.is_looping() && !rewound {
+                    transport.seek(0.0);
+                    rewound = true;
+                } else {
+                    transport.set_playing(false);
+                }
+                continue;
+            }
+            rewound = false;
+            transport
+                .sample_rate
+                .store(reader.sample_rate(), Ordering::Relaxed);
+            transport
+                .channels
+                .store(reader.channels(), Ordering::Relaxed);
+            let frames = (data.len() / reader.channels().max(1)) as u64;
+            transport.position.fetch_add(frames, Ordering::Relaxed);
+            if tx.send(data).is_err() {
+                //接收端没了 说明Audio已经被丢掉了
+                break;
+            }
+            next += block_time;
+            let now = Instant::now();
+            if next > now {
+                std::thread::sleep(next - now);
+            } else {
+                //落后太多就不追了 免得一下子灌进去一大堆
+                next = now;
+            }
+        }
+        Ok(())
+    }
+}
+impl AudioSource for PlaybackSource {
+    fn start(&mut self, tx: mpsc::Sender<Vec<f32>>) -> Result<(), anyhow::Error> {
+        //暂停之后再开始只需要继续播放
+        if self.thread.is_some() {
+            self.transport.set_playing(true);
+            return Ok(());
+        }
+        let reader = self
+            .reader
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("音源已经被关闭"))?;
+        let transport = self.transport.clone();
+        let quit = self.quit.clone();
+        self.thread = Some(std::thread::spawn(move || {
+            if let Err(e) = PlaybackSource::run(reader, transport.clone(), quit, tx) {
+                transport.set_playing(false);
+                *transport.error.lock().unwrap() = Some(e.to_string());
+            }
+        }));
+        Ok(())
+    }
+    fn stop(&mut self) {
+        self.transport.set_playing(false);
+    }
+    fn sample_rate(&self) -> u32 {
+        self.transport.sample_rate.load(Ordering::Relaxed)
+    }
+    fn channels(&self) -> usize {
+        self.transport.channels.load(Ordering::Relaxed)
+    }
+    fn transport(&self) -> Option<Arc<Transport>> {
+        Some(self.transport.clone())
+    }
+}
+impl Drop for PlaybackSource {
+    fn drop(&mut self) {
+        self.quit.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wav::WavFile;
+
+    //一帧都没有的wav 开着循环也要停下来 不能一直从头读空数据
+    #[test]
+    fn empty_file_stops_when_looping() {
+        let path = std::env::temp_dir().join(format!("playback-empty-{}.wav", std::process::id()));
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 48000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        hound::WavWriter::create(&path, spec)
+            .unwrap()
+            .finalize()
+            .unwrap();
+        let mut source = PlaybackSource::new(Box::new(WavFile::open(&path).unwrap()));
+        let transport = source.transport().unwrap();
+        transport.set_looping(true);
+        let (tx, _rx) = mpsc::channel();
+        source.start(tx).unwrap();
+        let begin = Instant::now();
+        while transport.is_playing() {
+            assert!(begin.elapsed() < Duration::from_secs(1), "一直在循环");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(transport.error(), None);
+        drop(source);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    //读了第一块才知道真正采样率的解码器
+    struct LateFormat {
+        read: bool,
+    }
+    impl FrameReader for LateFormat {
+        fn read(&mut self, frames: usize) -> Result<Vec<f32>, anyhow::Error> {
+            self.read = true;
+            Ok(vec![0.0; frames * 2])
+        }
+        fn seek(&mut self, _frame: u64) -> Result<(), anyhow::Error> {
+            Ok(())
+        }
+        fn sample_rate(&self) -> u32 {
+            if self.read {
+                44100
+            } else {
+                48000
+            }
+        }
+        fn channels(&self) -> usize {
+            if self.read {
+                2
+            } else {
+                1
+            }
+        }
+        fn length(&self) -> Option<u64> {
+            None
+        }
+    }
+
+    //音源报的格式跟着读出来的数据走
+    #[test]
+    fn format_follows_reader() {
+        let mut source = PlaybackSource::new(Box::new(LateFormat { read: false }));
+        assert_eq!((source.sample_rate(), source.channels()), (48000, 1));
+        let (tx, rx) = mpsc::channel();
+        source.start(tx).unwrap();
+        rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!((source.sample_rate(), source.channels()), (44100, 2));
+    }
+}
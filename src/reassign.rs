@@ -0,0 +1,249 @@
+use std::{collections::VecDeque, f32::consts::TAU, sync::Arc};
+
+use realfft::{num_complex::Complex, RealFftPlanner, RealToComplex};
+
+use crate::audio::SpectrumUnit;
+
+//重排的方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReassignMode {
+    Synchrosqueeze, //只在频率方向上挪 没有延迟
+    Reassign,       //时间和频率都挪 输出要晚半个窗长
+}
+
+/*
+时频重排
+普通的短时傅里叶变换把能量摊在窗的主瓣那么宽的一片上 重排就是把每个点的能量挪到它真正的重心上
+除了原来的窗h 再用窗的导数dh和乘了时间的窗th各做一次fft
+频率重心 ω̂ = ω - Im(X_dh·X_h* / |X_h|²)
+时间重心 t̂ = t + Re(X_th·X_h* / |X_h|²)
+挪完之后按重心把能量加到对应的那一列和那个频点上
+*/
+pub struct Reassigner {
+    pub mode: ReassignMode,
+    pub fftsize: usize,
+    pub window: Vec<f32>, //原来的窗 和FftCache里的一样 变了就要重建
+    pub hop: usize,
+    dh: Vec<f32>, //窗对时间的导数 单位是每个采样
+    th: Vec<f32>, //(n-中心)·h(n) 单位是采样
+    enbw: f32,    //等效噪声带宽 单位是频点
+    plan: Arc<dyn RealToComplex<f32>>,
+    input: Vec<f32>,
+    output_dh: Vec<Complex<f32>>,
+    output_th: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+    delay: usize,                   //时间最多往前往后挪几列
+    lanes: Vec<VecDeque<Vec<f32>>>, //每一路还没输出的列 中间那列是当前帧
+}
+impl Reassigner {
+    pub fn new(
+        planner: &mut RealFftPlanner<f32>,
+        mode: ReassignMode,
+        fftsize: usize,
+        window: &[f32],
+        hop: usize,
+    ) -> Self {
+        let len = window.len();
+        let at = |n: isize| -> f32 {
+            if n < 0 || n >= len as isize {
+                0.0
+            } else {
+                window[n as usize]
+            }
+        };
+        //中心差分 窗外面当成0
+        let dh = (0..len as isize)
+            .map(|n| (at(n + 1) - at(n - 1)) / 2.0)
+            .collect();
+        let middle = (len as f32 - 1.0) / 2.0;
+        let th = window
+            .iter()
+            .enumerate()
+            .map(|(n, w)| (n as f32 - middle) * w)
+            .collect();
+        let sum: f32 = window.iter().sum();
+        let power: f32 = window.iter().map(|w| w * w).sum();
+        let hop = hop.max(1);
+        let plan = planner.plan_fft_forward(fftsize);
+        Self {
+            mode,
+            fftsize,
+            window: window.to_vec(),
+            hop,
+            dh,
+            th,
+            enbw: fftsize as f32 * power / (sum * sum).max(f32::MIN_POSITIVE),
+            input: plan.make_input_vec(),
+            output_dh: plan.make_output_vec(),
+            output_th: plan.make_output_vec(),
+            scratch: plan.make_scratch_vec(),
+            plan,
+            delay: match mode {
+                ReassignMode::Synchrosqueeze => 0,
+                ReassignMode::Reassign => len.div_ceil(2 * hop),
+            },
+            lanes: Vec::new(),
+        }
+    }
+    //用另一个窗做一次fft 结果放到output里
+    fn transform(
+        plan: &Arc<dyn RealToComplex<f32>>,
+        input: &mut [f32],
+        scratch: &mut [Complex<f32>],
+        frame: &[f32],
+        window: &[f32],
+        output: &mut [Complex<f32>],
+    ) {
+        let (head, tail) = input.split_at_mut(window.len());
+        for ((x, s), w) in head.iter_mut().zip(frame).zip(window) {
+            *x = s * w;
+        }
+        tail.fill(0.0);
+        plan.process_with_scratch(input, output, scratch).unwrap();
+    }
+    /*
+    spectrum是原来的窗做出来的X_h power是校准过的单边功率
+    返回这一路重排好的一列 线性功率 Reassign模式下是delay帧以前的那一列
+    */
+    pub fn process(
+        &mut self,
+        lane: usize,
+        frame: &[f32],
+        spectrum: &[Complex<f32>],
+        power: &[f32],
+        unit: SpectrumUnit,
+    ) -> Vec<f32> {
+        let bins = spectrum.len();
+        if self.lanes.len() <= lane {
+            let empty: VecDeque<Vec<f32>> = (0..self.delay * 2).map(|_| vec![0.0; bins]).collect();
+            self.lanes.resize(lane + 1, empty);
+        }
+        Self::transform(
+            &self.plan,
+            &mut self.input,
+            &mut self.scratch,
+            frame,
+            &self.dh,
+            &mut self.output_dh,
+        );
+        if self.mode == ReassignMode::Reassign {
+            Self::transform(
+                &self.plan,
+                &mut self.input,
+                &mut self.scratch,
+                frame,
+                &self.th,
+                &mut self.output_th,
+            );
+        }
+        //幅度谱下正弦主瓣里的能量会全部挪到一个点上 要除掉主瓣宽度 读数才和原来的峰值一样
+        //功率谱密度下能量守恒 不用管
+        let scale = match unit {
+            SpectrumUnit::Dbfs => 1.0 / self.enbw,
+            SpectrumUnit::Psd => 1.0,
+        };
+        let columns = &mut self.lanes[lane];
+        columns.push_back(vec![0.0; bins]);
+        let max = power.iter().copied().fold(0.0, f32::max);
+        for (k, (x, p)) in spectrum.iter().zip(power).enumerate() {
+            let norm = x.norm_sqr();
+            //太小的点重心算不准 直接扔掉
+            if norm <= f32::MIN_POSITIVE || *p < max * 1e-12 {
+                continue;
+            }
+            let ratio = self.output_dh[k] * x.conj() / norm;
+            let bin = k as f32 - ratio.im * self.fftsize as f32 / TAU;
+            if !(0.0..=(bins - 1) as f32).contains(&bin) {
+                continue;
+            }
+            let column = match self.mode {
+                ReassignMode::Synchrosqueeze => 0,
+                ReassignMode::Reassign => {
+                    let shift = (self.output_th[k] * x.conj() / norm).re / self.hop as f32;
+                    let d = self.delay as isize;
+                    (d + shift.round() as isize).clamp(0, 2 * d) as usize
+                }
+            };
+            //落在两个频点之间的 按距离分给两边 分的是功率 两边的权重加起来是1 总功率不变
+            let low = bin.floor() as usize;
+            let t = bin - low as f32;
+            let energy = p * scale;
+            let target = &mut columns[column];
+            target[low] += energy * (1.0 - t);
+            if t > 0.0 {
+                target[low + 1] += energy * t;
+            }
+        }
+        columns.pop_front().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::window::FFTWindow;
+
+    //和Audio::do_fft一样 算出X_h和校准过的dBFS功率
+    fn analyze(
+        plan: &Arc<dyn RealToComplex<f32>>,
+        frame: &[f32],
+        window: &[f32],
+    ) -> (Vec<Complex<f32>>, Vec<f32>) {
+        let mut input: Vec<f32> = frame.iter().zip(window).map(|(s, w)| s * w).collect();
+        let mut output = plan.make_output_vec();
+        plan.process(&mut input, &mut output).unwrap();
+        let sum: f32 = window.iter().sum();
+        let last = output.len() - 1;
+        let power = output
+            .iter()
+            .enumerate()
+            .map(|(k, x)| {
+                let fold = if k == 0 || k == last { 1.0 } else { 4.0 };
+                x.norm_sqr() * fold / (sum * sum)
+            })
+            .collect();
+        (output, power)
+    }
+
+    //正弦不在频点上 重排以后挪到两个频点上的功率加起来还是满幅的0dB
+    #[test]
+    fn off_bin_tone_keeps_its_level() {
+        let fftsize = 1024;
+        let window = FFTWindow::Hanning.table(fftsize);
+        let mut planner = RealFftPlanner::new();
+        let plan = planner.plan_fft_forward(fftsize);
+        for mode in [ReassignMode::Synchrosqueeze, ReassignMode::Reassign] {
+            for offset in [0.0, 0.25, 0.5] {
+                let bin = 100.0 + offset;
+                let hop = fftsize / 4;
+                let mut reassigner = Reassigner::new(&mut planner, mode, fftsize, &window, hop);
+                let mut column = Vec::new();
+                //稳定的正弦 每一列都一样 多推几帧把延迟走完
+                for i in 0..8 {
+                    let frame: Vec<f32> = (0..fftsize)
+                        .map(|n| {
+                            let t = (i * hop + n) as f32;
+                            (TAU * bin * t / fftsize as f32).sin()
+                        })
+                        .collect();
+                    let (spectrum, power) = analyze(&plan, &frame, &window);
+                    column = reassigner.process(0, &frame, &spectrum, &power, SpectrumUnit::Dbfs);
+                }
+                let total: f32 = column[95..106].iter().sum();
+                let level = 10.0 * total.log10();
+                assert!(
+                    level.abs() < 0.1,
+                    "{mode:?} 偏{offset}个频点 读数是 {level} dB"
+                );
+                //挪完以后应该只剩正弦两边那两个频点
+                let near = column[100] + column[101];
+                assert!(
+                    near > total * 0.99,
+                    "{mode:?} 偏{offset}个频点 没有挪到一起"
+                );
+                let outside: f32 = column.iter().sum::<f32>() - total;
+                assert!(outside < total * 1e-3, "{mode:?} 有功率挪到别处去了");
+            }
+        }
+    }
+}
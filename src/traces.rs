@@ -0,0 +1,96 @@
+//频谱曲线要画的几条线 一路一个 从上次重置开始累计
+pub struct Traces {
+    pub current: Vec<f32>,
+    pub max: Vec<f32>,
+    pub min: Vec<f32>,
+    sum: Vec<f64>, //平均在线性功率上做
+    count: usize,
+    db: bool,
+}
+impl Traces {
+    fn new(bins: usize, db: bool) -> Self {
+        Self {
+            current: Vec::new(),
+            max: vec![f32::MIN; bins],
+            min: vec![f32::MAX; bins],
+            sum: vec![0.0; bins],
+            count: 0,
+            db,
+        }
+    }
+    //从开始到现在的平均
+    pub fn average(&self) -> Vec<f32> {
+        let n = self.count.max(1) as f64;
+        self.sum
+            .iter()
+            .map(|s| {
+                let p = s / n;
+                if self.db {
+                    (10.0 * (p + 1e-20).log10()) as f32
+                } else {
+                    p as f32
+                }
+            })
+            .collect()
+    }
+    fn push(&mut self, spectrum: &[f32]) {
+        for (((s, max), min), x) in self
+            .sum
+            .iter_mut()
+            .zip(&mut self.max)
+            .zip(&mut self.min)
+            .zip(spectrum)
+        {
+            *s += if self.db {
+                10f64.powf(*x as f64 / 10.0)
+            } else {
+                *x as f64
+            };
+            *max = max.max(*x);
+            *min = min.min(*x);
+        }
+        self.count += 1;
+        self.current = spectrum.to_vec();
+    }
+}
+
+//每一路的曲线 路数、频点数、单位变了就自己重置
+#[derive(Default)]
+pub struct SpectrumTraces {
+    pub lanes: Vec<Traces>,
+}
+impl SpectrumTraces {
+    pub fn reset(&mut self) {
+        self.lanes.clear();
+    }
+    pub fn push(&mut self, spectrums: &[Vec<f32>], db: bool) {
+        let bins = spectrums.first().map_or(0, |s| s.len());
+        let changed = self.lanes.len() != spectrums.len()
+            || self.lanes.iter().any(|l| l.sum.len() != bins || l.db != db);
+        if changed {
+            self.lanes = spectrums.iter().map(|_| Traces::new(bins, db)).collect();
+        }
+        for (lane, spectrum) in self.lanes.iter_mut().zip(spectrums) {
+            lane.push(spectrum);
+        }
+    }
+}
+
+//频点太多的时候每组取一个代表 最大值曲线取组里最大的 最小值曲线取最小的
+//返回(组中间的频点, 值)
+pub fn decimate(values: &[f32], points: usize, pick_min: bool) -> Vec<(f32, f32)> {
+    let group = values.len().div_ceil(points.max(1)).max(1);
+    values
+        .chunks(group)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let value = if pick_min {
+                chunk.iter().copied().fold(f32::MAX, f32::min)
+            } else {
+                chunk.iter().copied().fold(f32::MIN, f32::max)
+            };
+            let center = (i * group) as f32 + (chunk.len() - 1) as f32 / 2.0;
+            (center, value)
+        })
+        .collect()
+}
@@ -0,0 +1,69 @@
+use std::{fs::File, io::BufReader, path::Path};
+
+use hound::{SampleFormat, WavReader};
+
+use crate::playback::FrameReader;
+
+//用hound读wav文件 支持8/16/24/32位整数和32位浮点 任意声道数
+pub struct WavFile {
+    reader: WavReader<BufReader<File>>,
+    channels: usize,
+    sample_rate: u32,
+    format: SampleFormat,
+    scale: f32, //整数样本除以它变成-1到1
+}
+impl WavFile {
+    pub fn open(path: &Path) -> Result<Self, anyhow::Error> {
+        let reader = WavReader::open(path)?;
+        let spec = reader.spec();
+        if spec.sample_format == SampleFormat::Float && spec.bits_per_sample != 32 {
+            return Err(anyhow::anyhow!(
+                "不支持{}位浮点的wav文件",
+                spec.bits_per_sample
+            ));
+        }
+        Ok(Self {
+            reader,
+            channels: spec.channels as usize,
+            sample_rate: spec.sample_rate,
+            format: spec.sample_format,
+            scale: (1u64 << (spec.bits_per_sample - 1)) as f32,
+        })
+    }
+}
+impl FrameReader for WavFile {
+    fn read(&mut self, frames: usize) -> Result<Vec<f32>, anyhow::Error> {
+        let count = frames * self.channels;
+        let data = match self.format {
+            SampleFormat::Float => self
+                .reader
+                .samples::<f32>()
+                .take(count)
+                .collect::<Result<Vec<f32>, _>>()?,
+            //8位的wav是无符号的 hound读出来的时候已经转成有符号的了
+            SampleFormat::Int => {
+                let scale = self.scale;
+                self.reader
+                    .samples::<i32>()
+                    .take(count)
+                    .map(|s| s.map(|s| s as f32 / scale))
+                    .collect::<Result<Vec<f32>, _>>()?
+            }
+        };
+        Ok(data)
+    }
+    fn seek(&mut self, frame: u64) -> Result<(), anyhow::Error> {
+        let frame = frame.min(self.reader.duration() as u64) as u32;
+        self.reader.seek(frame)?;
+        Ok(())
+    }
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+    fn channels(&self) -> usize {
+        self.channels
+    }
+    fn length(&self) -> Option<u64> {
+        Some(self.reader.duration() as u64)
+    }
+}
@@ -1,6 +1,7 @@
 use std::sync::Arc;
 
 use crate::{
+    args::Args,
     compute::Compute,
     egui_app::EguiApp,
 };
@@ -54,6 +55,8 @@ impl<'window> WGPUState<'window> {
                 &wgpu::DeviceDescriptor {
                     label: None,
                     required_features: wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES, //我不需要在web上运行 而且这样可以帮助我使用writeable的纹理
+                    //默认限制纹理最高8192 放不下16384点fft的8193个频点 用显卡实际支持的
+                    required_limits: adapter.limits(),
                     ..Default::default()
                 },
                 None,
@@ -176,14 +179,16 @@ pub struct WGPUAPP<'a> {
     audio_compute: Option<Compute>,
     pub height: u32,
     scale: (f32, f32),
+    args: Args,
 }
 impl<'a> WGPUAPP<'a> {
-    pub fn new() -> Self {
+    pub fn new(args: Args) -> Self {
         Self {
+            args,
             state: None,
             appgui: None,
             audio_compute: None,
-            height: 1024 / 2, //这里要和初始的fftsize的一半保持一致
+            height: 1024 / 2 + 1, //这里要和初始的fftsize的频点数保持一致
             scale: (0.0, 1.0),
         }
     }
@@ -203,6 +208,7 @@ impl<'a> WGPUAPP<'a> {
             self.state.as_ref().unwrap().surface_config.format,
             None,
             1,
+            &self.args,
         ));
         self.audio_compute = Some(Compute::new(self.state.as_ref().unwrap(), self.height));
     }
@@ -224,6 +230,9 @@ impl<'a> WGPUAPP<'a> {
             .unwrap()
             .on_resize(state, self.height);
     }
+    pub fn pane_count(&self) -> usize {
+        self.audio_compute.as_ref().map_or(1, |c| c.pane_count())
+    }
     pub fn set_scale_parameters(&mut self, scale: (f32, f32)) {
         self.scale = scale;
     }
@@ -253,9 +262,18 @@ impl<'a> WGPUAPP<'a> {
 
         //如果有新数据 上计算pass
         while let Some(d) = self.appgui.as_mut().unwrap().get_audio_stream_data() {
-            if self.height != d.1 / 2 as u32 {
-                //如果fftsize发生了改变 那么通过计算pass更变高度
-                self.height = d.1 / 2 as u32;
+            //路数变了就重新分配窗格
+            if self.audio_compute.as_ref().unwrap().pane_count() != d.0.len() {
+                self.audio_compute
+                    .as_mut()
+                    .unwrap()
+                    .set_pane_count(state, d.0.len());
+            }
+            //频点太多的时候纹理高度受显卡限制 计算着色器里会把多个频点合到一行
+            let bins = (d.0[0].len() as u32).min(state.device.limits().max_texture_dimension_2d);
+            if self.height != bins {
+                //如果fft的频点数发生了改变 那么通过计算pass更变高度
+                self.height = bins;
                 self.audio_compute
                     .as_mut()
                     .unwrap()
@@ -272,7 +290,7 @@ impl<'a> WGPUAPP<'a> {
             self.audio_compute
                 .as_mut()
                 .unwrap()
-                .update_data(&state.queue, d.0.as_slice(), d.2);
+                .update_data(&state.queue, &d.0, d.2);
             self.audio_compute
                 .as_mut()
                 .unwrap()
@@ -286,8 +304,6 @@ impl<'a> WGPUAPP<'a> {
             });
 
         //把计算pass得到的纹理变成sampler
-        let texture = self.audio_compute.as_mut().unwrap().output();
-        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
         let sampler = state.device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
             address_mode_v: wgpu::AddressMode::ClampToEdge,
@@ -298,29 +314,38 @@ impl<'a> WGPUAPP<'a> {
             ..Default::default()
         });
 
-        //创建bindgroup
-        let bind_group = state.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &state.bindgroup_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::TextureView(&texture_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::Sampler(&sampler),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::Buffer(BufferBinding {
-                        buffer: &state.buffer,
-                        offset: 0,
-                        size: None,
-                    }),
-                },
-            ],
-            label: Some("diffuse_bind_group"),
-        });
+        //每个窗格一个bindgroup 缩放参数大家共用
+        let bind_groups: Vec<wgpu::BindGroup> = self
+            .audio_compute
+            .as_ref()
+            .unwrap()
+            .outputs()
+            .map(|texture| {
+                let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+                state.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &state.bindgroup_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::TextureView(&texture_view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::Sampler(&sampler),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: wgpu::BindingResource::Buffer(BufferBinding {
+                                buffer: &state.buffer,
+                                offset: 0,
+                                size: None,
+                            }),
+                        },
+                    ],
+                    label: Some("diffuse_bind_group"),
+                })
+            })
+            .collect();
         //然后是渲染的
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
@@ -343,8 +368,18 @@ impl<'a> WGPUAPP<'a> {
                 occlusion_query_set: None,
             });
             render_pass.set_pipeline(&state.pipeline);
-            render_pass.set_bind_group(0, &bind_group, &[]);
-            render_pass.draw(0..3, 0..1);
+            //窗格从上到下排列 中间留一点缝隙
+            const GAP: f32 = 4.0;
+            let width = state.surface_config.width as f32;
+            let count = bind_groups.len() as f32;
+            let pane_height =
+                ((state.surface_config.height as f32 - GAP * (count - 1.0)) / count).max(1.0);
+            for (i, bind_group) in bind_groups.iter().enumerate() {
+                let y = i as f32 * (pane_height + GAP);
+                render_pass.set_viewport(0.0, y, width, pane_height, 0.0, 1.0);
+                render_pass.set_bind_group(0, bind_group, &[]);
+                render_pass.draw(0..3, 0..1);
+            }
         }
         self.appgui.as_mut().unwrap().update(state)(&mut encoder, &view); //最后渲染的是ui
 
@@ -0,0 +1,269 @@
+use std::f64::consts::PI;
+
+use rustfft::{num_complex::Complex, FftPlanner};
+
+//平顶窗的系数 和matlab的flattopwin一样
+const FLAT_TOP: [f64; 5] = [
+    0.21557895,
+    0.41663158,
+    0.277263158,
+    0.083578947,
+    0.006947368,
+];
+
+//窗函数 带参数的把参数直接放在里面
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FFTWindow {
+    Rectangular,
+    Hanning,
+    Hamming,
+    Blackman,
+    BlackmanHarris,
+    Nuttall,
+    FlatTop,             //平顶窗 主瓣很平 测幅值准
+    Kaiser(f32),         //β 越大旁瓣越低 主瓣越宽
+    Tukey(f32),          //α 两边余弦过渡占的比例 0是矩形窗 1是汉宁窗
+    Gaussian(f32),       //σ 相对半个窗长
+    DolphChebyshev(f32), //旁瓣比主瓣低多少dB 所有旁瓣一样高
+}
+impl FFTWindow {
+    //下拉框里列出来的 带参数的用默认参数
+    pub const ALL: [FFTWindow; 11] = [
+        FFTWindow::Rectangular,
+        FFTWindow::Hanning,
+        FFTWindow::Hamming,
+        FFTWindow::Blackman,
+        FFTWindow::BlackmanHarris,
+        FFTWindow::Nuttall,
+        FFTWindow::FlatTop,
+        FFTWindow::Kaiser(8.6),
+        FFTWindow::Tukey(0.5),
+        FFTWindow::Gaussian(0.4),
+        FFTWindow::DolphChebyshev(100.0),
+    ];
+    pub fn name(&self) -> &'static str {
+        match self {
+            FFTWindow::Rectangular => "Rectangular",
+            FFTWindow::Hanning => "Hanning",
+            FFTWindow::Hamming => "Hamming",
+            FFTWindow::Blackman => "Blackman",
+            FFTWindow::BlackmanHarris => "Blackman-Harris",
+            FFTWindow::Nuttall => "Nuttall",
+            FFTWindow::FlatTop => "Flat-top",
+            FFTWindow::Kaiser(_) => "Kaiser",
+            FFTWindow::Tukey(_) => "Tukey",
+            FFTWindow::Gaussian(_) => "Gaussian",
+            FFTWindow::DolphChebyshev(_) => "Dolph-Chebyshev",
+        }
+    }
+    //同一种窗 不管参数是多少
+    pub fn same_kind(&self, other: &FFTWindow) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+    //可调的参数 给界面用 返回参数名和范围
+    pub fn parameter_mut(
+        &mut self,
+    ) -> Option<(&'static str, &mut f32, std::ops::RangeInclusive<f32>)> {
+        match self {
+            FFTWindow::Kaiser(beta) => Some(("β", beta, 0.0..=30.0)),
+            FFTWindow::Tukey(alpha) => Some(("α", alpha, 0.0..=1.0)),
+            FFTWindow::Gaussian(sigma) => Some(("σ", sigma, 0.05..=1.0)),
+            FFTWindow::DolphChebyshev(db) => Some(("旁瓣 dB", db, 20.0..=200.0)),
+            _ => None,
+        }
+    }
+    //算一整张长度为len的窗函数表 都是对称的窗
+    pub fn table(&self, len: usize) -> Vec<f32> {
+        if len <= 1 {
+            return vec![1.0; len];
+        }
+        if let FFTWindow::DolphChebyshev(db) = *self {
+            return chebyshev(len, db as f64);
+        }
+        let m = (len - 1) as f64;
+        //n归一化到0到1
+        let window = |n: f64| -> f64 {
+            match *self {
+                FFTWindow::Rectangular => 1.0,
+                FFTWindow::Hanning => cosine_sum(n, &[0.5, 0.5]),
+                FFTWindow::Hamming => cosine_sum(n, &[0.54, 0.46]),
+                FFTWindow::Blackman => cosine_sum(n, &[0.42, 0.5, 0.08]),
+                FFTWindow::BlackmanHarris => cosine_sum(n, &[0.35875, 0.48829, 0.14128, 0.01168]),
+                FFTWindow::Nuttall => cosine_sum(n, &[0.355768, 0.487396, 0.144232, 0.012604]),
+                FFTWindow::FlatTop => cosine_sum(n, &FLAT_TOP),
+                FFTWindow::Kaiser(beta) => {
+                    let beta = beta as f64;
+                    let r = 2.0 * n - 1.0;
+                    bessel_i0(beta * (1.0 - r * r).max(0.0).sqrt()) / bessel_i0(beta)
+                }
+                FFTWindow::Tukey(alpha) => {
+                    let alpha = alpha.clamp(0.0, 1.0) as f64;
+                    //离两头的距离 在α/2以内的部分是余弦过渡
+                    let edge = n.min(1.0 - n);
+                    if alpha == 0.0 || edge >= alpha / 2.0 {
+                        1.0
+                    } else {
+                        0.5 * (1.0 - (2.0 * PI * edge / alpha).cos())
+                    }
+                }
+                FFTWindow::Gaussian(sigma) => {
+                    let x = (2.0 * n - 1.0) / sigma.max(0.01) as f64;
+                    (-0.5 * x * x).exp()
+                }
+                FFTWindow::DolphChebyshev(_) => unreachable!(),
+            }
+        };
+        (0..len).map(|n| window(n as f64 / m) as f32).collect()
+    }
+}
+
+//a0 - a1 cos(2πn) + a2 cos(4πn) - ... 汉宁、布莱克曼这一类都是这个形式
+fn cosine_sum(n: f64, a: &[f64]) -> f64 {
+    a.iter()
+        .enumerate()
+        .map(|(k, a)| {
+            let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+            sign * a * (2.0 * PI * k as f64 * n).cos()
+        })
+        .sum()
+}
+
+//第一类零阶修正贝塞尔函数 级数展开 加到项足够小为止
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half = x / 2.0;
+    for k in 1..500 {
+        term *= (half / k as f64) * (half / k as f64);
+        sum += term;
+        if term < sum * 1e-16 {
+            break;
+        }
+    }
+    sum
+}
+
+//Dolph-Chebyshev窗 在频域用切比雪夫多项式构造等波纹的旁瓣 再用fft变回时域
+//做法和scipy的chebwin一样
+fn chebyshev(len: usize, db: f64) -> Vec<f32> {
+    let order = (len - 1) as f64;
+    let beta = ((10f64.powf(db / 20.0)).acosh() / order).cosh();
+    let mut buffer: Vec<Complex<f64>> = (0..len)
+        .map(|k| {
+            let x = beta * (PI * k as f64 / len as f64).cos();
+            let t = if x > 1.0 {
+                (order * x.acosh()).cosh()
+            } else if x < -1.0 {
+                //偶数长度的时候阶数是奇数 切比雪夫多项式是奇函数
+                let sign = if len % 2 == 1 { 1.0 } else { -1.0 };
+                sign * (order * (-x).acosh()).cosh()
+            } else {
+                (order * x.acos()).cos()
+            };
+            if len % 2 == 1 {
+                Complex::new(t, 0.0)
+            } else {
+                //偶数长度要移半个采样 中心才落在两个点中间
+                Complex::from_polar(t, PI * k as f64 / len as f64)
+            }
+        })
+        .collect();
+    FftPlanner::new().plan_fft_forward(len).process(&mut buffer);
+    let w: Vec<f64> = buffer.iter().map(|c| c.re).collect();
+    //fft出来的是以0为中心的一半 对称拼成完整的窗
+    let half = if len % 2 == 1 {
+        len.div_ceil(2)
+    } else {
+        len / 2 + 1
+    };
+    let mut window: Vec<f64> = w[1..half].iter().rev().copied().collect();
+    if len % 2 == 1 {
+        window.extend_from_slice(&w[..half]);
+    } else {
+        window.extend_from_slice(&w[1..half]);
+    }
+    let max = window.iter().copied().fold(f64::MIN, f64::max);
+    window.iter().map(|w| (w / max) as f32).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //相干增益Σw/N和等效噪声带宽NΣw²/(Σw)² 单位是频点
+    fn gain_and_enbw(window: FFTWindow) -> (f64, f64) {
+        let table = window.table(4096);
+        let sum: f64 = table.iter().map(|&w| w as f64).sum();
+        let power: f64 = table.iter().map(|&w| w as f64 * w as f64).sum();
+        (sum / 4096.0, 4096.0 * power / (sum * sum))
+    }
+
+    //和教科书上的值对一下 对称窗和周期窗差1/N 所以容差给宽一点
+    #[test]
+    fn coherent_gain_and_enbw() {
+        for (window, gain, enbw) in [
+            (FFTWindow::Rectangular, 1.0, 1.0),
+            (FFTWindow::Hanning, 0.5, 1.5),
+            (FFTWindow::Hamming, 0.54, 1.3628),
+            (FFTWindow::Blackman, 0.42, 1.7268),
+            (FFTWindow::BlackmanHarris, 0.35875, 2.0044),
+            (FFTWindow::FlatTop, 0.21557895, 3.7702),
+        ] {
+            let (g, e) = gain_and_enbw(window);
+            assert!((g - gain).abs() < 1e-3, "{} 相干增益 {g}", window.name());
+            assert!(
+                (e - enbw).abs() < 2e-3,
+                "{} 等效噪声带宽 {e}",
+                window.name()
+            );
+        }
+    }
+
+    //参考值照scipy.signal.windows.chebwin的算法用直接求和的dft算的
+    #[test]
+    fn chebyshev_matches_scipy() {
+        for expected in [
+            &[
+                0.077447, 0.276047, 0.583523, 0.877604, 1.0, 0.877604, 0.583523, 0.276047, 0.077447,
+            ][..],
+            &[
+                0.094551, 0.349375, 0.718224, 1.0, 1.0, 0.718224, 0.349375, 0.094551,
+            ][..],
+        ] {
+            let table = FFTWindow::DolphChebyshev(50.0).table(expected.len());
+            for (w, e) in table.iter().zip(expected) {
+                assert!((*w as f64 - e).abs() < 1e-5, "{table:?}");
+            }
+        }
+    }
+
+    //所有旁瓣一样高 都比主瓣低设定的dB数
+    #[test]
+    fn chebyshev_sidelobes_are_flat() {
+        let table = FFTWindow::DolphChebyshev(60.0).table(31);
+        let response: Vec<f64> = (0..=2048)
+            .map(|i| {
+                let f = PI * i as f64 / 2048.0;
+                let c: Complex<f64> = table
+                    .iter()
+                    .enumerate()
+                    .map(|(n, &w)| Complex::from_polar(w as f64, -f * n as f64))
+                    .sum();
+                c.norm()
+            })
+            .collect();
+        //主瓣一直往下走到第一个零点
+        let edge = (1..response.len())
+            .find(|&i| response[i] > response[i - 1])
+            .unwrap();
+        let db = |a: f64| 20.0 * (a / response[0]).log10();
+        let highest = response[edge..].iter().copied().fold(0.0, f64::max);
+        assert!((db(highest) + 60.0).abs() < 0.1, "{}", db(highest));
+        //最后一个旁瓣也一样高 说明没有往下掉
+        let last = response[response.len() - 100..]
+            .iter()
+            .copied()
+            .fold(0.0, f64::max);
+        assert!((db(last) + 60.0).abs() < 0.5, "{}", db(last));
+    }
+}
@@ -7,7 +7,7 @@ use winit::{
     window::Window,
 };
 
-use crate::wgpu_app::WGPUAPP;
+use crate::{args::Args, wgpu_app::WGPUAPP};
 
 pub struct App<'a> {
     window: Option<Arc<Window>>,
@@ -24,10 +24,10 @@ impl<'a> App<'a> {
         self.window = Some(window);
         self.app.init(self.window.clone().unwrap());
     }
-    pub fn new() -> Self {
+    pub fn new(args: Args) -> Self {
         Self {
             window: None,
-            app: WGPUAPP::new(),
+            app: WGPUAPP::new(args),
             scale: (0.0, 1.0),
             mouse_position: PhysicalPosition::new(0.0, 0.0),
         }
@@ -84,8 +84,12 @@ impl ApplicationHandler for App<'_> {
                     }
                 }
                 //算出当前单位坐标系下鼠标的y轴坐标 和wgpu中保持一致
-                let y = self.mouse_position.y as f32
-                    / self.window.as_mut().unwrap().inner_size().height as f32;
+                //多个窗格共用同一个缩放 所以算的是鼠标在它所在窗格内的位置
+                let panes = self.app.pane_count().max(1) as f32;
+                let y = (self.mouse_position.y as f32
+                    / self.window.as_mut().unwrap().inner_size().height as f32
+                    * panes)
+                    .fract();
 
                 //算出鼠标在缩放后的视窗内的坐标
                 let t = self.scale.0 + y * (self.scale.1 - self.scale.0);
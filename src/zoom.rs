@@ -0,0 +1,235 @@
+use std::{f64::consts::TAU, sync::Arc};
+
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+
+use crate::window::FFTWindow;
+
+//缩放fft的参数 看center附近 宽度是采样率/decimation的一段
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ZoomSettings {
+    pub center: f32,       //中心频率 Hz
+    pub decimation: usize, //抽取倍数
+}
+
+/*
+一路信号的混频、低通、抽取
+先乘e^(-jωt)把中心频率搬到0 再用低通滤掉带外的 然后每decimation个点留一个
+滤波器只在要留下的那个点上算 所以每个输入点的计算量和抽取倍数无关
+*/
+struct Decimator {
+    taps: Arc<Vec<f32>>,
+    step: f64,  //每个采样相位走多少 单位是周
+    phase: f64, //本振的相位
+    factor: usize,
+    counter: usize,
+    history: Vec<Complex<f32>>, //混频后的数据 最后taps.len()个是滤波要用的
+}
+impl Decimator {
+    fn process(&mut self, input: &[f32], output: &mut Vec<Complex<f32>>) {
+        let n = self.taps.len();
+        for &x in input {
+            let (sin, cos) = (-self.phase * TAU).sin_cos();
+            self.history
+                .push(Complex::new(x * cos as f32, x * sin as f32));
+            self.phase = (self.phase + self.step).fract();
+            self.counter += 1;
+            if self.counter == self.factor {
+                self.counter = 0;
+                if self.history.len() >= n {
+                    //系数是对称的 不用倒过来
+                    let recent = &self.history[self.history.len() - n..];
+                    let y = recent
+                        .iter()
+                        .zip(self.taps.iter())
+                        .fold(Complex::new(0.0, 0.0), |acc, (h, t)| acc + h * t);
+                    output.push(y);
+                }
+            }
+        }
+        //只留滤波需要的那一段 攒多一点再删 免得每次都挪内存
+        if self.history.len() > n * 4 {
+            self.history.drain(..self.history.len() - n);
+        }
+    }
+}
+
+//凯泽窗设计的低通 截止在新采样率的奈奎斯特附近
+//通带到0.4倍新采样率 0.5倍以外衰减80dB 所以显示出来的频带最边上的一点会往下掉
+fn lowpass(decimation: usize) -> Vec<f32> {
+    let len = 50 * decimation + 1;
+    let cutoff = 0.45 / decimation as f64; //相对于原采样率
+    let window = FFTWindow::Kaiser(7.86).table(len);
+    let middle = (len / 2) as f64;
+    let mut taps: Vec<f64> = window
+        .iter()
+        .enumerate()
+        .map(|(i, w)| {
+            let t = i as f64 - middle;
+            let sinc = if t == 0.0 {
+                2.0 * cutoff
+            } else {
+                (TAU * cutoff * t).sin() / (std::f64::consts::PI * t)
+            };
+            sinc * *w as f64
+        })
+        .collect();
+    //直流增益归一化成1
+    let sum: f64 = taps.iter().sum();
+    taps.iter_mut().for_each(|t| *t /= sum);
+    taps.into_iter().map(|t| t as f32).collect()
+}
+
+//缩放fft 每一路信号先各自抽取到复数基带 然后做复数fft
+pub struct Zoom {
+    pub settings: ZoomSettings,
+    pub sample_rate: u32, //原始的采样率
+    taps: Arc<Vec<f32>>,
+    lanes: Vec<Decimator>,
+    buffers: Vec<Vec<Complex<f32>>>, //每一路抽取后还没做fft的数据
+    planner: FftPlanner<f32>,
+    plan: Option<Arc<dyn Fft<f32>>>,
+    buffer: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+}
+impl Zoom {
+    pub fn new(settings: ZoomSettings, sample_rate: u32) -> Self {
+        let settings = ZoomSettings {
+            decimation: settings.decimation.max(1),
+            ..settings
+        };
+        Self {
+            taps: Arc::new(lowpass(settings.decimation)),
+            settings,
+            sample_rate,
+            lanes: Vec::new(),
+            buffers: Vec::new(),
+            planner: FftPlanner::new(),
+            plan: None,
+            buffer: Vec::new(),
+            scratch: Vec::new(),
+        }
+    }
+    //抽取后的采样率
+    pub fn output_rate(&self) -> f32 {
+        self.sample_rate as f32 / self.settings.decimation as f32
+    }
+    //路数变了之类的 之前的状态全部丢掉
+    pub fn reset(&mut self) {
+        self.lanes.clear();
+        self.buffers.clear();
+    }
+    pub fn push(&mut self, lanes: &[Vec<f32>]) {
+        if self.lanes.len() != lanes.len() {
+            let step = self.settings.center as f64 / self.sample_rate as f64;
+            self.lanes = (0..lanes.len())
+                .map(|_| Decimator {
+                    taps: self.taps.clone(),
+                    step,
+                    phase: 0.0,
+                    factor: self.settings.decimation,
+                    counter: 0,
+                    history: Vec::new(),
+                })
+                .collect();
+            self.buffers = vec![Vec::new(); lanes.len()];
+        }
+        for ((decimator, buffer), lane) in self.lanes.iter_mut().zip(&mut self.buffers).zip(lanes) {
+            decimator.process(lane, buffer);
+        }
+    }
+    //每一路还没处理的抽取后采样数
+    pub fn remain(&self) -> Option<usize> {
+        self.buffers.first().map(|b| b.len())
+    }
+    //和实数的一样 取一帧 往前走hop
+    pub fn take_frames(&mut self, window_len: usize, hop: usize) -> Vec<Vec<Complex<f32>>> {
+        self.buffers
+            .iter_mut()
+            .map(|buffer| {
+                let frame = buffer[..window_len].to_vec();
+                buffer.drain(..hop);
+                frame
+            })
+            .collect()
+    }
+    //加窗做复数fft 返回|X|² 负频率挪到前面 中心频率在正中间
+    pub fn power_spectrum(
+        &mut self,
+        frame: &[Complex<f32>],
+        window: &[f32],
+        fftsize: usize,
+    ) -> Vec<f32> {
+        if self.plan.as_ref().map(|p| p.len()) != Some(fftsize) {
+            let plan = self.planner.plan_fft_forward(fftsize);
+            self.scratch = vec![Complex::new(0.0, 0.0); plan.get_inplace_scratch_len()];
+            self.plan = Some(plan);
+        }
+        self.buffer.clear();
+        self.buffer
+            .extend(frame.iter().zip(window).map(|(x, w)| x * w));
+        self.buffer.resize(fftsize, Complex::new(0.0, 0.0));
+        self.plan
+            .as_ref()
+            .unwrap()
+            .process_with_scratch(&mut self.buffer, &mut self.scratch);
+        //直流落在fftsize/2上
+        let split = fftsize - fftsize / 2;
+        self.buffer[split..]
+            .iter()
+            .chain(&self.buffer[..split])
+            .map(|x| x.norm_sqr())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //48k抽取8倍 中心5kHz 看的是2kHz到8kHz
+    fn decimate(frequency: f64, chunk: usize) -> Vec<Complex<f32>> {
+        let mut zoom = Zoom::new(
+            ZoomSettings {
+                center: 5000.0,
+                decimation: 8,
+            },
+            48000,
+        );
+        let input: Vec<f32> = (0..48000)
+            .map(|n| (TAU * frequency * n as f64 / 48000.0).cos() as f32)
+            .collect();
+        for block in input.chunks(chunk) {
+            zoom.push(&[block.to_vec()]);
+        }
+        zoom.buffers.remove(0)
+    }
+
+    //分多少块送进去结果都一样 滤波器填满之后每8个点出一个
+    #[test]
+    fn chunking_does_not_matter() {
+        let whole = decimate(5500.0, 48000);
+        assert_eq!(whole.len(), (48000 - 401) / 8 + 1);
+        assert_eq!(decimate(5500.0, 37), whole);
+        assert_eq!(decimate(5500.0, 1), whole);
+    }
+
+    //中心上面500Hz的余弦搬到500Hz 只剩正频率那一半 幅度是0.5
+    #[test]
+    fn passband_tone_is_shifted_to_baseband() {
+        let output = decimate(5500.0, 4800);
+        let step = TAU * 500.0 / 6000.0;
+        for pair in output.windows(2) {
+            assert!((pair[1].norm() - 0.5).abs() < 1e-3, "{}", pair[1].norm());
+            let turn = (pair[1] / pair[0]).arg() as f64;
+            assert!((turn - step).abs() < 1e-3, "{turn}");
+        }
+    }
+
+    //新的奈奎斯特以外的要衰减掉 不然会混叠进来
+    #[test]
+    fn stopband_tone_is_rejected() {
+        let output = decimate(8500.0, 4800);
+        let peak = output.iter().map(|x| x.norm()).fold(0.0, f32::max);
+        assert!(20.0 * (peak / 0.5).log10() < -70.0, "{peak}");
+    }
+}